@@ -35,5 +35,126 @@ pub enum PresaleError {
     #[msg("Cannot buy. Token limit exceeded")]
     ExceedsDepositAmount,
     #[msg("Exact payment required")]
-    ExactPaymentRequired
+    ExactPaymentRequired,
+    #[msg("Invalid vesting schedule")]
+    InvalidVestingSchedule,
+    #[msg("No vested tokens available to claim")]
+    NothingToClaim,
+    #[msg("Buyer is not on the whitelist")]
+    NotWhitelisted,
+    #[msg("Purchase exceeds whitelisted allocation")]
+    AllocationExceeded,
+    #[msg("Purchase is below the minimum amount")]
+    BelowMinimumPurchase,
+    #[msg("Wallet contribution limit exceeded")]
+    WalletLimitExceeded,
+    #[msg("Wallet token limit exceeded")]
+    WalletTokenLimitExceeded,
+    #[msg("Vault received less than the amount paid")]
+    TransferFeeMismatch,
+    #[msg("Quote mint is not accepted by this presale")]
+    UnsupportedQuoteMint,
+    #[msg("Quote mint is disabled")]
+    QuoteMintDisabled,
+    #[msg("Quote mint already added")]
+    QuoteMintAlreadyAdded,
+    #[msg("Too many quote mints")]
+    TooManyQuoteMints,
+    // No longer raised, kept so the codes after it do not shift
+    #[msg("Quote mint decimals must match the primary quote mint")]
+    QuoteDecimalsMismatch,
+    #[msg("Nothing to refund")]
+    NothingToRefund,
+    #[msg("SOL payments are not enabled")]
+    SolPaymentsDisabled,
+    #[msg("Invalid price feed")]
+    InvalidPriceFeed,
+    #[msg("Price feed is stale")]
+    StalePrice,
+    #[msg("Price confidence interval too wide")]
+    PriceConfidenceTooWide,
+    #[msg("Transaction deadline has passed")]
+    DeadlineExceeded,
+    #[msg("Tokens received below the requested minimum")]
+    SlippageExceeded,
+    #[msg("Purchase would reach a level above the requested maximum")]
+    MaxLevelExceeded,
+    #[msg("No tokens available at the requested levels")]
+    NoTokensAvailable,
+    #[msg("Presale status does not allow this action")]
+    InvalidStatusTransition,
+    #[msg("Presale is paused")]
+    PresalePaused,
+    #[msg("Operation is paused")]
+    OperationPaused,
+    #[msg("Invalid pause mask")]
+    InvalidPauseMask,
+    #[msg("No admin transfer is pending for this key")]
+    NoPendingAdmin,
+    #[msg("Start time must be before end time")]
+    InvalidTimeRange,
+    #[msg("Softcap must not exceed the hardcap")]
+    SoftCapAboveHardCap,
+    #[msg("Only end time extensions, hardcap raises and new levels are allowed while live")]
+    NotEditableWhileLive,
+    #[msg("Mint can no longer be changed")]
+    MintChangeNotAllowed,
+    #[msg("End time must be in the future")]
+    EndTimeInPast,
+    #[msg("Hardcap must be greater than zero")]
+    ZeroHardCap,
+    #[msg("Every level with tokens needs a non-zero price")]
+    ZeroLevelPrice,
+    #[msg("At least one level must have tokens")]
+    NoLevelsConfigured,
+    #[msg("Hardcap exceeds the tokens available across levels")]
+    HardCapExceedsLevels,
+    #[msg("Level token totals exceed the deposit amount")]
+    LevelTotalExceedsDeposit,
+    #[msg("Level token totals must equal the deposit amount")]
+    LevelTotalMismatch,
+    #[msg("A new presale cannot start with tokens sold")]
+    SoldAmountNotZero,
+    #[msg("Token mint argument does not match the token mint account")]
+    TokenMintMismatch,
+    #[msg("USD mint argument does not match the USD mint account")]
+    UsdMintMismatch,
+    #[msg("Too many price levels")]
+    TooManyLevels,
+    #[msg("Level soft cap exceeds the value of the level's tokens")]
+    LevelSoftCapUnreachable,
+    #[msg("Instruction not available in this sale mode")]
+    WrongSaleMode,
+    #[msg("Invalid sale mode parameters")]
+    InvalidSaleMode,
+    #[msg("No commitment left to settle")]
+    NothingToSettle,
+    #[msg("Price ticks must be empty levels with strictly increasing prices")]
+    InvalidPriceTicks,
+    #[msg("Bid price tick does not exist")]
+    InvalidBidLevel,
+    #[msg("Auction clearing price is not settled yet")]
+    AuctionNotSettled,
+    #[msg("Auction clearing price is already settled")]
+    AuctionAlreadySettled,
+    #[msg("Buyers cannot refer themselves")]
+    SelfReferral,
+    #[msg("Referral belongs to another presale")]
+    ReferralMismatch,
+    #[msg("Referral commission must not exceed 20%")]
+    InvalidReferralConfig,
+    #[msg("Referral program is not enabled")]
+    ReferralsDisabled,
+    #[msg("Wrapped SOL payments need the wSOL mint, both wSOL accounts and the token program")]
+    MissingWsolAccounts,
+    #[msg("Presale must be finalized first")]
+    PresaleNotFinalized,
+    #[msg("Sold or bonus tokens have not all been claimed")]
+    TokensStillOwed,
+    #[msg("Quote is still held for buyers or referrers")]
+    QuoteStillReserved,
+    #[msg("Deposited tokens do not cover the tokens for sale")]
+    PresaleNotFunded,
+    #[msg("Commitments earn no referral commission")]
+    ReferralNotSupported
 }
\ No newline at end of file
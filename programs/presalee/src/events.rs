@@ -0,0 +1,241 @@
+use anchor_lang::prelude::*;
+
+use crate::state::{LevelFill, PresaleRole, PresaleStatus};
+
+#[event]
+pub struct PresaleCreated {
+    pub presale: Pubkey,
+    pub admin: Pubkey,
+    pub token_mint_address: Pubkey,
+    pub usd_mint: Pubkey,
+    pub softcap_amount: u64,
+    pub hardcap_amount: u64,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub auto_start: bool,
+}
+
+#[event]
+pub struct PresaleUpdated {
+    pub presale: Pubkey,
+    pub status: PresaleStatus,
+}
+
+#[event]
+pub struct PresaleStarted {
+    pub presale: Pubkey,
+    pub start_time: u64,
+}
+
+#[event]
+pub struct PresaleEnded {
+    pub presale: Pubkey,
+    pub end_time: u64,
+    pub sold_token_amount: u64,
+}
+
+#[event]
+pub struct PresalePaused {
+    pub presale: Pubkey,
+    pub pause_flags: u8,
+    pub paused_at: u64,
+}
+
+#[event]
+pub struct PresaleResumed {
+    pub presale: Pubkey,
+    pub pause_flags: u8,
+    pub end_time: u64,
+}
+
+#[event]
+pub struct PresaleCancelled {
+    pub presale: Pubkey,
+}
+
+#[event]
+pub struct PresaleClosed {
+    pub presale: Pubkey,
+}
+
+#[event]
+pub struct TokensDeposited {
+    pub presale: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct TokensPurchased {
+    pub presale: Pubkey,
+    pub buyer: Pubkey,
+    pub quote_mint: Pubkey,
+    pub amount_spent: u64,
+    pub tokens_bought: u64,
+    pub levels: Vec<LevelFill>,
+    pub timestamp: u64,
+}
+
+#[event]
+pub struct QuoteCommitted {
+    pub presale: Pubkey,
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub total_committed: u64,
+    pub timestamp: u64,
+}
+
+#[event]
+pub struct CommitmentSettled {
+    pub presale: Pubkey,
+    pub buyer: Pubkey,
+    pub tokens: u64,
+    pub amount_spent: u64,
+    pub refund: u64,
+}
+
+#[event]
+pub struct RebateClaimed {
+    pub presale: Pubkey,
+    pub buyer: Pubkey,
+    pub clearing_price: u64,
+    pub amount_paid: u64,
+    pub rebate: u64,
+}
+
+#[event]
+pub struct BidPlaced {
+    pub presale: Pubkey,
+    pub bidder: Pubkey,
+    pub level: u8,
+    pub price: u64,
+    pub quantity: u64,
+    pub deposit: u64,
+    pub timestamp: u64,
+}
+
+#[event]
+pub struct BidCancelled {
+    pub presale: Pubkey,
+    pub bidder: Pubkey,
+    pub level: u8,
+    pub quantity: u64,
+    pub refund: u64,
+}
+
+#[event]
+pub struct AuctionSettled {
+    pub presale: Pubkey,
+    pub clearing_price: u64,
+    pub total_demand: u64,
+    pub tokens_sold: u64,
+}
+
+#[event]
+pub struct BidSettled {
+    pub presale: Pubkey,
+    pub bidder: Pubkey,
+    pub tokens: u64,
+    pub amount_spent: u64,
+    pub refund: u64,
+}
+
+#[event]
+pub struct ReferrerRegistered {
+    pub presale: Pubkey,
+    pub referrer: Pubkey,
+}
+
+#[event]
+pub struct ReferralCredited {
+    pub presale: Pubkey,
+    pub referrer: Pubkey,
+    pub buyer: Pubkey,
+    pub amount_spent: u64,
+    pub commission: u64,
+}
+
+#[event]
+pub struct ReferralClaimed {
+    pub presale: Pubkey,
+    pub referrer: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct TokensPurchasedWithSol {
+    pub presale: Pubkey,
+    pub buyer: Pubkey,
+    pub lamports_spent: u64,
+    // Quote value of the lamports at the oracle price
+    pub amount_spent: u64,
+    pub tokens_bought: u64,
+    pub levels: Vec<LevelFill>,
+    pub timestamp: u64,
+}
+
+#[event]
+pub struct TokensClaimed {
+    pub presale: Pubkey,
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub total_claimed: u64,
+}
+
+#[event]
+pub struct RefundClaimed {
+    pub presale: Pubkey,
+    pub buyer: Pubkey,
+    pub quote_mint: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct SolRefundClaimed {
+    pub presale: Pubkey,
+    pub buyer: Pubkey,
+    pub lamports: u64,
+}
+
+#[event]
+pub struct TokensWithdrawn {
+    pub presale: Pubkey,
+    pub admin: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct QuoteWithdrawn {
+    pub presale: Pubkey,
+    pub admin: Pubkey,
+    pub quote_mint: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct SolWithdrawn {
+    pub presale: Pubkey,
+    pub admin: Pubkey,
+    pub lamports: u64,
+}
+
+#[event]
+pub struct AdminProposed {
+    pub presale: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct AdminTransferred {
+    pub presale: Pubkey,
+    pub previous_authority: Pubkey,
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct RoleUpdated {
+    pub presale: Pubkey,
+    pub role: PresaleRole,
+    pub key: Pubkey,
+}
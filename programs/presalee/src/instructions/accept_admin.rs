@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::PresaleError, events::AdminTransferred, state::Presale};
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    #[account(mut)]
+    pub new_admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+        constraint = presale.pending_authority == Some(new_admin.key()) @ PresaleError::NoPendingAdmin,
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+impl<'info> AcceptAdmin<'info> {
+    pub fn accept_admin(&mut self) -> Result<()> {
+        // The PDA keeps its creator seed, only the controlling key moves
+        let previous_authority = self.presale.authority;
+        self.presale.authority = self.new_admin.key();
+        self.presale.pending_authority = None;
+
+        emit!(AdminTransferred {
+            presale: self.presale.key(),
+            previous_authority,
+            authority: self.presale.authority,
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{associated_token::AssociatedToken, token_interface::{Mint, TokenAccount, TokenInterface}};
+
+use crate::{errors::PresaleError, state::{Presale, QuoteMint, MAX_QUOTE_MINTS}};
+
+#[derive(Accounts)]
+pub struct AddQuoteMint<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub usd_mint: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program)]
+    pub quote_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = presale.authority == admin.key() @ PresaleError::Unauthorized,
+        has_one = usd_mint,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        associated_token::mint = quote_mint,
+        associated_token::authority = presale,
+        associated_token::token_program = token_program
+    )]
+    pub vault_quote: InterfaceAccount<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+impl<'info> AddQuoteMint<'info> {
+    pub fn add_quote_mint(&mut self) -> Result<()> {
+        let presale = &mut self.presale;
+        let mint = self.quote_mint.key();
+
+        require!(presale.quote_index(&mint).is_err(), PresaleError::QuoteMintAlreadyAdded);
+        require!(presale.quote_mints.len() < MAX_QUOTE_MINTS, PresaleError::TooManyQuoteMints);
+
+        // Levels are priced per whole token, so a mint with other decimals pays the same price
+        presale.quote_mints.push(QuoteMint::new(mint, self.quote_mint.decimals));
+
+        Ok(())
+    }
+}
@@ -1,22 +1,23 @@
 use anchor_lang::prelude::*;
-use anchor_spl::{associated_token::AssociatedToken, token::{
-        Mint, TokenAccount, Token, TransferChecked, transfer_checked
+use anchor_spl::{associated_token::AssociatedToken, token_interface::{
+        transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked
     },
 };
 
-use crate::{errors::PresaleError, state::{Presale, UserInfo}};
+use crate::{errors::PresaleError, events::{QuoteCommitted, ReferralCredited, TokensPurchased}, state::{FillMode, LevelFill, Presale, Referral, SaleMode, UserInfo}, utils::amount_with_transfer_fee};
 
 #[derive(Accounts)]
  pub struct BuyToken <'info> {
     #[account(mut)]
     pub buyer: Signer<'info>,
-    pub token_mint_address: Account<'info, Mint>,
-    pub usd_mint: Account<'info, Mint>,
+    pub token_mint_address: InterfaceAccount<'info, Mint>,
+    // Any enabled quote mint of the presale
+    #[account(mint::token_program = token_program)]
+    pub usd_mint: InterfaceAccount<'info, Mint>,
 
     #[account(
         mut,
         has_one = token_mint_address,
-        has_one = usd_mint,
         seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
         bump = presale.bump
     )]
@@ -26,16 +27,18 @@ use crate::{errors::PresaleError, state::{Presale, UserInfo}};
         init_if_needed,
         payer = buyer,
         associated_token::mint = usd_mint,
-        associated_token::authority = buyer
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
     )]
-    pub buyer_ata: Account<'info, TokenAccount>,
+    pub buyer_ata: InterfaceAccount<'info, TokenAccount>,
      #[account(
         init_if_needed,
         payer = buyer,
         associated_token::mint = usd_mint,
-        associated_token::authority = presale
+        associated_token::authority = presale,
+        associated_token::token_program = token_program
     )]
-    pub vault_usd: Account<'info, TokenAccount>,
+    pub vault_usd: InterfaceAccount<'info, TokenAccount>,
     #[account(
         init_if_needed,
         payer = buyer,
@@ -44,129 +47,219 @@ use crate::{errors::PresaleError, state::{Presale, UserInfo}};
         bump
     )]
     pub user: Account<'info, UserInfo>,
+    // Referrer the buyer names, if any
+    #[account(
+        mut,
+        has_one = presale @ PresaleError::ReferralMismatch,
+        constraint = referral.referrer != buyer.key() @ PresaleError::SelfReferral,
+    )]
+    pub referral: Option<Account<'info, Referral>>,
 
     pub system_program: Program<'info, System>,
     pub associated_token_program: Program<'info, AssociatedToken>,
-    pub token_program: Program<'info, Token>
+    pub token_program: Interface<'info, TokenInterface>
  }
 
  impl <'info> BuyToken <'info> {
-    pub fn buy_tokens(&mut self, payment: u64) -> Result<()> {
+    pub fn buy_tokens(
+        &mut self,
+        payment: u64,
+        min_tokens_out: u64,
+        max_level: Option<u8>,
+        deadline: u64,
+        fill_mode: FillMode,
+        max_allocation: u64,
+        proof: Vec<[u8; 32]>,
+        bumps: &BuyTokenBumps,
+    ) -> Result<()> {
+        let (quote_index, current_time) = self.check_buyer(deadline, max_allocation, &proof, bumps)?;
+
+        let (total_tokens_bought, remaining_payment, level_fills) = self.presale.fill_payment(payment, self.usd_mint.decimals, max_level, current_time)?;
+
+        // Check if we couldn't spend all the payment (acceptable for partial fills or once hardcapped)
+        self.presale.check_fill(total_tokens_bought, remaining_payment, max_level, fill_mode)?;
+
+        // Honour the price the buyer was quoted
+        require!(total_tokens_bought >= min_tokens_out, PresaleError::SlippageExceeded);
+
+        // Calculate actual amount spent
+        let amount_spent = payment - remaining_payment;
+
+        self.settle_purchase(quote_index, amount_spent, total_tokens_bought, level_fills, max_allocation, current_time)?;
+
+        msg!("Transaction completed: total_tokens_bought={}, usd_spent={}, remaining_payment={}", 
+             total_tokens_bought, amount_spent, remaining_payment);
+
+        Ok(())
+    }
+
+    // Exact-output sibling of `buy_tokens`: the buyer names the tokens and the most they will pay
+    pub fn buy_exact_tokens(
+        &mut self,
+        token_amount: u64,
+        max_payment: u64,
+        max_level: Option<u8>,
+        deadline: u64,
+        max_allocation: u64,
+        proof: Vec<[u8; 32]>,
+        bumps: &BuyTokenBumps,
+    ) -> Result<()> {
+        let (quote_index, current_time) = self.check_buyer(deadline, max_allocation, &proof, bumps)?;
+
+        require!(token_amount > 0, PresaleError::NoTokensAvailable);
+        let (cost, level_fills) = self.presale.fill_exact_out(token_amount, self.usd_mint.decimals, max_level, current_time)?;
+
+        // Honour the price the buyer was quoted
+        require!(cost <= max_payment, PresaleError::SlippageExceeded);
+
+        self.settle_purchase(quote_index, cost, token_amount, level_fills, max_allocation, current_time)?;
+
+        msg!("Transaction completed: total_tokens_bought={}, usd_spent={}", token_amount, cost);
+
+        Ok(())
+    }
+
+    // Overflow-mode purchase: `amount` of the primary quote mint is committed now and turned into
+    // tokens plus a refund by `settle_commitment` once the sale succeeds. Most of a commitment
+    // may come back, so no referral commission is credited on it and `referral` must be unset.
+    pub fn commit_quote(
+        &mut self,
+        amount: u64,
+        deadline: u64,
+        max_allocation: u64,
+        proof: Vec<[u8; 32]>,
+        bumps: &BuyTokenBumps,
+    ) -> Result<()> {
+        require!(matches!(self.presale.sale_mode, SaleMode::Overflow { .. }), PresaleError::WrongSaleMode);
+        let (quote_index, current_time) = self.check_buyer(deadline, max_allocation, &proof, bumps)?;
+        require!(quote_index == 0, PresaleError::UnsupportedQuoteMint);
+        require!(self.referral.is_none(), PresaleError::ReferralNotSupported);
+        require!(amount > 0, PresaleError::BelowMinimumPurchase);
+
+        self.presale.check_purchase_limits(&self.user, amount, 0, max_allocation)?;
+        self.collect_payment(amount)?;
+
+        let quote = &mut self.presale.quote_mints[quote_index];
+        quote.raised_amount = quote.raised_amount
+            .checked_add(amount)
+            .ok_or(PresaleError::MathOverflow)?;
+        quote.reserved_amount = quote.reserved_amount
+            .checked_add(amount)
+            .ok_or(PresaleError::MathOverflow)?;
+        self.presale.sold_token_amount = self.presale.overflow_tokens_sold()?;
+
+        self.user.record_purchase(self.buyer.key(), amount, 0, current_time)?;
+        self.user.quote_amounts[quote_index] = self.user.quote_amounts[quote_index]
+            .checked_add(amount)
+            .ok_or(PresaleError::MathOverflow)?;
+
+        emit!(QuoteCommitted {
+            presale: self.presale.key(),
+            buyer: self.buyer.key(),
+            amount,
+            total_committed: self.presale.committed_amount(),
+            timestamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    // Checks shared by every purchase, returns the quote slot paid in and the current time
+    fn check_buyer(&mut self, deadline: u64, max_allocation: u64, proof: &[[u8; 32]], bumps: &BuyTokenBumps) -> Result<(usize, u64)> {
         let clock = Clock::get()?;
         let current_time = clock.unix_timestamp as u64;
         
-        require!(self.presale.is_live, PresaleError::PresaleNotStarted);
-        require!(current_time < self.presale.end_time, PresaleError::PresaleEnded);
-
-        let mut remaining_payment = payment;
-        let mut total_tokens_bought = 0 as u64;
-
-        // Continue buying until payment is exhausted or all levels are sold out
-        while remaining_payment > 0 && (self.presale.current_level as usize) < self.presale.levels.len() {
-            let current_level_index = self.presale.current_level as usize;
-            
-            // Read current level data
-            let level_token_amount = self.presale.levels[current_level_index].token_amount;
-            let level_tokens_sold = self.presale.levels[current_level_index].tokens_sold;
-            let level_price = self.presale.levels[current_level_index].price;
-            let tokens_remaining_in_level = level_token_amount - level_tokens_sold;
-            
-            msg!(
-                "Level {} status: total_tokens={}, sold={}, remaining={}, price={}",
-                current_level_index,
-                level_token_amount,
-                level_tokens_sold,
-                tokens_remaining_in_level,
-                level_price
-            );
-            
-            // If current level is exhausted, move to next level
-            if tokens_remaining_in_level == 0 {
-                msg!("Level {} exhausted, moving to next level", current_level_index);
-                self.presale.current_level += 1;
-                continue;
-            }
-
-            // Calculate how many tokens can be bought with remaining payment at current price
-            // Formula: tokens = (payment * 10^6) / price  (assuming 6 decimals for tokens)
-            let tokens_can_afford = remaining_payment
-                .checked_mul(1_000_000) // Scale up payment (6 decimals)
-                .and_then(|x| x.checked_div(level_price))
-                .ok_or(PresaleError::CalculationOverflow)?;
-            
-            // Take minimum of what can be afforded and what's available in this level
-            let tokens_to_buy = tokens_can_afford.min(tokens_remaining_in_level);
-            
-            // Calculate exact cost for these tokens
-            let cost_for_tokens = tokens_to_buy
-                .checked_mul(level_price)
-                .and_then(|x| x.checked_div(1_000_000))
-                .ok_or(PresaleError::CalculationOverflow)?;
-
-            msg!(
-                "Purchase calculation: can_afford={}, will_buy={}, cost={}, remaining_payment={}",
-                tokens_can_afford,
-                tokens_to_buy,
-                cost_for_tokens,
-                remaining_payment
-            );
-
-            // Verify we don't exceed hardcap
-            require!(
-                (self.presale.sold_token_amount + tokens_to_buy) <= self.presale.hardcap_amount,
-                PresaleError::HardCapped
-            );
-
-            // Verify we don't exceed deposited tokens
-            require!(
-                (self.presale.sold_token_amount + tokens_to_buy) <= self.presale.deposit_token_amount,
-                PresaleError::ExceedsDepositAmount
-            );
-
-            // Update the current level and presale state
-            self.presale.levels[current_level_index].tokens_sold += tokens_to_buy;
-            self.presale.sold_token_amount += tokens_to_buy;
-            total_tokens_bought += tokens_to_buy;
-
-            remaining_payment -= cost_for_tokens;
-
-            // Update presale flags
-            self.presale.is_soft_capped = self.presale.sold_token_amount >= self.presale.softcap_amount;
-            self.presale.is_hard_capped = self.presale.sold_token_amount >= self.presale.hardcap_amount;
-
-            msg!(
-                "After purchase: level_tokens_sold={}, total_sold={}, tokens_bought_this_tx={}, remaining_payment={}",
-                self.presale.levels[current_level_index].tokens_sold,
-                self.presale.sold_token_amount,
-                total_tokens_bought,
-                remaining_payment
-            );
-
-            // If current level is now exhausted, move to next level for next iteration
-            if self.presale.levels[current_level_index].tokens_sold >= level_token_amount {
-                msg!("Level {} completed, moving to next level", current_level_index);
-                self.presale.current_level += 1;
-            }
-
-            // If we've hit hardcap, break out of loop
-            if self.presale.is_hard_capped {
-                break;
-            }
+        self.presale.require_live(current_time)?;
+        require!(current_time <= deadline, PresaleError::DeadlineExceeded);
+
+        let quote_index = self.presale.quote_index(&self.usd_mint.key())?;
+        require!(self.presale.quote_mints[quote_index].enabled, PresaleError::QuoteMintDisabled);
+        // Rebates are settled against the clearing price in the primary quote mint
+        require!(!self.presale.has_rebates() || quote_index == 0, PresaleError::UnsupportedQuoteMint);
+
+        self.presale.check_whitelist(&self.buyer.key(), max_allocation, proof)?;
+
+        // Claims check the account against its stored bump, including when the buy created it
+        self.user.bump = bumps.user;
+
+        Ok((quote_index, current_time))
+    }
+
+    // Collects `amount_spent` from the buyer and credits the purchase to their account
+    fn settle_purchase(
+        &mut self,
+        quote_index: usize,
+        amount_spent: u64,
+        total_tokens_bought: u64,
+        level_fills: Vec<LevelFill>,
+        max_allocation: u64,
+        current_time: u64,
+    ) -> Result<()> {
+        // Limits and the buyer's running total count in the primary mint's units
+        let primary_amount = self.presale.primary_quote_amount(quote_index, amount_spent)?;
+        self.presale.check_purchase_limits(&self.user, primary_amount, total_tokens_bought, max_allocation)?;
+        self.collect_payment(amount_spent)?;
+
+        // Uniform-price auction payments stay reserved until the buyer's rebate is settled
+        let has_rebates = self.presale.has_rebates();
+        let quote = &mut self.presale.quote_mints[quote_index];
+        quote.raised_amount = quote.raised_amount
+            .checked_add(amount_spent)
+            .ok_or(PresaleError::MathOverflow)?;
+        if has_rebates {
+            quote.reserved_amount = quote.reserved_amount
+                .checked_add(amount_spent)
+                .ok_or(PresaleError::MathOverflow)?;
         }
 
-        // Check if we couldn't spend all the payment (this might be acceptable if hardcap reached)
-        if remaining_payment > 0 {
-            // If hardcap is reached, this is acceptable
-            if !self.presale.is_hard_capped {
-                msg!("Payment remaining: {} (all levels exhausted or insufficient tokens)", remaining_payment);
-                return Err(PresaleError::ExactPaymentRequired.into());
-            }
+        // Update user contribution
+        self.user.record_purchase(self.buyer.key(), primary_amount, total_tokens_bought, current_time)?;
+        self.presale.owe_tokens(total_tokens_bought)?;
+        self.user.quote_amounts[quote_index] = self.user.quote_amounts[quote_index]
+            .checked_add(amount_spent)
+            .ok_or(PresaleError::MathOverflow)?;
+
+        if let Some(referral) = self.referral.as_mut() {
+            let commission = self.presale.credit_referral(
+                referral,
+                quote_index,
+                self.usd_mint.decimals,
+                amount_spent,
+                total_tokens_bought,
+            )?;
+
+            emit!(ReferralCredited {
+                presale: self.presale.key(),
+                referrer: referral.referrer,
+                buyer: self.buyer.key(),
+                amount_spent,
+                commission,
+            });
         }
 
-        // Calculate actual amount spent
-        let amount_spent = payment - remaining_payment;
+        // Reaching the hardcap ends the sale right away
+        self.presale.sync_status(current_time);
+
+        emit!(TokensPurchased {
+            presale: self.presale.key(),
+            buyer: self.buyer.key(),
+            quote_mint: self.usd_mint.key(),
+            amount_spent,
+            tokens_bought: total_tokens_bought,
+            levels: level_fills,
+            timestamp: current_time,
+        });
+
+        Ok(())
+    }
 
-        // Transfer the spent amount from buyer to presale vault
+    // Moves `amount` of the quote mint from the buyer into the vault
+    fn collect_payment(&mut self, amount: u64) -> Result<()> {
+        // Buyer covers any Token-2022 transfer fee so the vault is credited the full amount
+        let vault_balance_before = self.vault_usd.amount;
+        let transfer_amount = amount_with_transfer_fee(&self.usd_mint.to_account_info(), amount)?;
+
+        // Transfer the amount from buyer to presale vault
         transfer_checked(
             CpiContext::new(
                 self.token_program.to_account_info(),
@@ -177,21 +270,16 @@ use crate::{errors::PresaleError, state::{Presale, UserInfo}};
                     authority: self.buyer.to_account_info(),
                 },
             ),
-            amount_spent,
+            transfer_amount,
             self.usd_mint.decimals
         )?;
 
-        // Update user contribution
-        let user_contribution = &mut self.user;
-        if user_contribution.buy_quote_amount == 0 {
-            user_contribution.buyer = self.buyer.key();
-        }
-        user_contribution.buy_quote_amount += amount_spent;
-        user_contribution.buy_token_amount += total_tokens_bought;
-
-        msg!("Transaction completed: total_tokens_bought={}, usd_spent={}, remaining_payment={}", 
-             total_tokens_bought, amount_spent, remaining_payment);
+        self.vault_usd.reload()?;
+        let amount_received = self.vault_usd.amount
+            .checked_sub(vault_balance_before)
+            .ok_or(PresaleError::MathOverflow)?;
+        require!(amount_received >= amount, PresaleError::TransferFeeMismatch);
 
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,203 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{transfer, Transfer};
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token::spl_token::native_mint,
+    token_interface::{close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::{errors::PresaleError, events::TokensPurchasedWithSol, state::{FillMode, Presale, UserInfo}, utils::OraclePrice};
+
+#[derive(Accounts)]
+pub struct BuyTokenSol<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    // Primary quote mint, levels are priced in its units
+    pub usd_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        has_one = usd_mint,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    /// CHECK: must be the SOL/USD feed configured on the presale, parsed in `OraclePrice::load`
+    #[account(
+        constraint = presale.sol_payment.map(|config| config.price_feed) == Some(price_feed.key()) @ PresaleError::InvalidPriceFeed
+    )]
+    pub price_feed: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"sol_vault", presale.key().as_ref()],
+        bump
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + UserInfo::INIT_SPACE,
+        seeds = [b"user", presale.key().as_ref(), buyer.key().as_ref() ],
+        bump
+    )]
+    pub user: Account<'info, UserInfo>,
+
+    // Wrapped SOL payments only: the buyer's wSOL account is debited instead of their lamports,
+    // through the presale's wSOL account which is unwrapped back to the buyer right away
+    #[account(address = native_mint::ID)]
+    pub wsol_mint: Option<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint = wsol_mint,
+        token::authority = buyer,
+        token::token_program = token_program
+    )]
+    pub buyer_wsol: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = wsol_mint,
+        associated_token::authority = presale,
+        associated_token::token_program = token_program
+    )]
+    pub wsol_transit: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> BuyTokenSol<'info> {
+    pub fn buy_tokens_with_sol(
+        &mut self,
+        lamports: u64,
+        min_tokens_out: u64,
+        max_level: Option<u8>,
+        deadline: u64,
+        fill_mode: FillMode,
+        max_allocation: u64,
+        proof: Vec<[u8; 32]>,
+        bumps: &BuyTokenSolBumps,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp as u64;
+
+        // Rebates are paid in the primary quote mint, so uniform-price auctions take no SOL
+        require!(!self.presale.has_rebates(), PresaleError::WrongSaleMode);
+        self.presale.require_live(current_time)?;
+        require!(current_time <= deadline, PresaleError::DeadlineExceeded);
+
+        let config = self.presale.sol_payment.ok_or(PresaleError::SolPaymentsDisabled)?;
+
+        self.presale.check_whitelist(&self.buyer.key(), max_allocation, &proof)?;
+
+        // Convert the SOL payment into quote units at the oracle price
+        let price = OraclePrice::load(&self.price_feed.to_account_info())?;
+        price.check(clock.unix_timestamp, config.max_price_age, config.max_confidence_bps)?;
+        let payment = price.lamports_to_quote(lamports, self.usd_mint.decimals)?;
+
+        msg!("SOL payment: lamports={}, price={}, expo={}, quote_value={}", lamports, price.price, price.expo, payment);
+
+        let (total_tokens_bought, remaining_payment, level_fills) = self.presale.fill_payment(payment, self.usd_mint.decimals, max_level, current_time)?;
+
+        // Same rule as quote purchases, unspent lamports are never charged
+        self.presale.check_fill(total_tokens_bought, remaining_payment, max_level, fill_mode)?;
+        require!(total_tokens_bought >= min_tokens_out, PresaleError::SlippageExceeded);
+
+        let amount_spent = payment - remaining_payment;
+        // Charge only the lamports covering the quote spent, rounded in the presale's favour
+        let lamports_spent = price.quote_to_lamports(amount_spent, self.usd_mint.decimals)?.min(lamports);
+
+        self.presale.check_purchase_limits(&self.user, amount_spent, total_tokens_bought, max_allocation)?;
+
+        if self.buyer_wsol.is_some() {
+            self.unwrap_wsol(lamports_spent)?;
+        }
+
+        transfer(
+            CpiContext::new(
+                self.system_program.to_account_info(),
+                Transfer {
+                    from: self.buyer.to_account_info(),
+                    to: self.sol_vault.to_account_info(),
+                },
+            ),
+            lamports_spent,
+        )?;
+
+        self.presale.sol_raised_amount = self.presale.sol_raised_amount
+            .checked_add(lamports_spent)
+            .ok_or(PresaleError::MathOverflow)?;
+
+        self.user.record_purchase(self.buyer.key(), amount_spent, total_tokens_bought, current_time)?;
+        self.presale.owe_tokens(total_tokens_bought)?;
+        self.user.sol_amount = self.user.sol_amount
+            .checked_add(lamports_spent)
+            .ok_or(PresaleError::MathOverflow)?;
+        self.user.bump = bumps.user;
+
+        // Reaching the hardcap ends the sale right away
+        self.presale.sync_status(current_time);
+
+        emit!(TokensPurchasedWithSol {
+            presale: self.presale.key(),
+            buyer: self.buyer.key(),
+            lamports_spent,
+            amount_spent,
+            tokens_bought: total_tokens_bought,
+            levels: level_fills,
+            timestamp: current_time,
+        });
+
+        msg!("Transaction completed: total_tokens_bought={}, usd_value={}, lamports_spent={}",
+             total_tokens_bought, amount_spent, lamports_spent);
+
+        Ok(())
+    }
+
+    // Turns `lamports` of the buyer's wSOL into native lamports in their wallet: the wSOL moves
+    // into the presale's transit account, which is then closed to the buyer with its rent
+    fn unwrap_wsol(&mut self, lamports: u64) -> Result<()> {
+        let (Some(wsol_mint), Some(buyer_wsol), Some(wsol_transit), Some(token_program)) =
+            (&self.wsol_mint, &self.buyer_wsol, &self.wsol_transit, &self.token_program)
+        else {
+            return Err(PresaleError::MissingWsolAccounts.into());
+        };
+
+        transfer_checked(
+            CpiContext::new(
+                token_program.to_account_info(),
+                TransferChecked {
+                    from: buyer_wsol.to_account_info(),
+                    mint: wsol_mint.to_account_info(),
+                    to: wsol_transit.to_account_info(),
+                    authority: self.buyer.to_account_info(),
+                },
+            ),
+            lamports,
+            wsol_mint.decimals
+        )?;
+
+        let binding = self.presale.admin.key();
+        let seeds = &[
+            &b"dogx_presale"[..],
+            binding.as_ref(),
+            &self.presale.seed.to_le_bytes(),
+            &[self.presale.bump],
+        ];
+        let signers_seeds = &[&seeds[..]];
+
+        close_account(CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            CloseAccount {
+                account: wsol_transit.to_account_info(),
+                destination: self.buyer.to_account_info(),
+                authority: self.presale.to_account_info(),
+            },
+            signers_seeds,
+        ))
+    }
+}
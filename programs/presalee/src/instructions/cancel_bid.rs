@@ -0,0 +1,108 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{associated_token::AssociatedToken, token_interface::{
+        transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked
+    },
+};
+
+use crate::{errors::PresaleError, events::BidCancelled, state::{Bid, Presale}};
+
+#[derive(Accounts)]
+pub struct CancelBid<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    #[account(mint::token_program = token_program)]
+    pub usd_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        has_one = usd_mint,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        associated_token::mint = usd_mint,
+        associated_token::authority = bidder,
+        associated_token::token_program = token_program
+    )]
+    pub bidder_ata: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = usd_mint,
+        associated_token::authority = presale,
+        associated_token::token_program = token_program
+    )]
+    pub vault_usd: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        close = bidder,
+        has_one = bidder,
+        seeds = [b"bid", presale.key().as_ref(), bidder.key().as_ref()],
+        bump = bid.bump
+    )]
+    pub bid: Account<'info, Bid>,
+
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>
+}
+
+impl<'info> CancelBid<'info> {
+    // Withdraws a bid while the auction runs, its deposit is paid back in full
+    pub fn cancel_bid(&mut self) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp as u64;
+        self.presale.require_live(current_time)?;
+
+        let bid = &self.bid;
+        let value = self.presale.token_value(bid.quantity, self.presale.levels[bid.level as usize].price)?;
+        let tick = &mut self.presale.levels[bid.level as usize];
+        tick.tokens_sold = tick.tokens_sold
+            .checked_sub(bid.quantity)
+            .ok_or(PresaleError::MathOverflow)?;
+        tick.raised = tick.raised.saturating_sub(value);
+        let quote = &mut self.presale.quote_mints[0];
+        quote.raised_amount = quote.raised_amount
+            .checked_sub(bid.deposit)
+            .ok_or(PresaleError::MathOverflow)?;
+        quote.reserved_amount = quote.reserved_amount
+            .checked_sub(bid.deposit)
+            .ok_or(PresaleError::MathOverflow)?;
+
+        let binding = self.presale.admin.key();
+        let seeds = &[
+            &b"dogx_presale"[..],
+            binding.as_ref(),
+            &self.presale.seed.to_le_bytes(),
+            &[self.presale.bump],
+        ];
+        let signers_seeds = &[&seeds[..]];
+
+        transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                TransferChecked {
+                    from: self.vault_usd.to_account_info(),
+                    mint: self.usd_mint.to_account_info(),
+                    to: self.bidder_ata.to_account_info(),
+                    authority: self.presale.to_account_info(),
+                },
+                signers_seeds,
+            ),
+            self.bid.deposit,
+            self.usd_mint.decimals
+        )?;
+
+        emit!(BidCancelled {
+            presale: self.presale.key(),
+            bidder: self.bidder.key(),
+            level: self.bid.level,
+            quantity: self.bid.quantity,
+            refund: self.bid.deposit,
+        });
+
+        Ok(())
+    }
+}
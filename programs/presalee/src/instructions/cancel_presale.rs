@@ -0,0 +1,32 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::PresaleError, events::PresaleCancelled, state::{Presale, PresaleAction}};
+
+#[derive(Accounts)]
+pub struct CancelPresale<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+        constraint = presale.authority == admin.key() @ PresaleError::Unauthorized,
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+impl<'info> CancelPresale<'info> {
+    pub fn cancel_presale(&mut self) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp as u64;
+
+        // Buyers get refunds, deposited tokens go back to the admin through withdraw_token
+        self.presale.transition(PresaleAction::Cancel, current_time)?;
+
+        emit!(PresaleCancelled {
+            presale: self.presale.key(),
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{associated_token::AssociatedToken, token_interface::{
+        transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked
+    },
+};
+
+use crate::{errors::PresaleError, events::RebateClaimed, state::{Presale, UserInfo, PAUSE_CLAIMS}};
+
+#[derive(Accounts)]
+pub struct ClaimRebate<'info> {
+    // Anyone may settle a buyer's rebate, it is always paid to the buyer
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: only used as the owner of the user account and the rebate ATA, receives the
+    /// user account's rent once nothing is owed to it
+    #[account(mut)]
+    pub buyer: UncheckedAccount<'info>,
+
+    // Primary quote mint, the only one uniform-price auctions take
+    #[account(mint::token_program = token_program)]
+    pub usd_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        has_one = usd_mint,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = usd_mint,
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
+    )]
+    pub buyer_ata: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = usd_mint,
+        associated_token::authority = presale,
+        associated_token::token_program = token_program
+    )]
+    pub vault_usd: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"user", presale.key().as_ref(), buyer.key().as_ref() ],
+        bump = user.bump,
+    )]
+    pub user: Account<'info, UserInfo>,
+
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> ClaimRebate<'info> {
+    // Uniform-price auctions charge every buyer the clearing price in the end, the difference
+    // with what they paid during the auction is returned here
+    pub fn claim_rebate(&mut self) -> Result<()> {
+        let presale = &mut self.presale;
+        let current_time = Clock::get()?.unix_timestamp as u64;
+        presale.require_succeeded(current_time)?;
+        presale.require_not_paused(PAUSE_CLAIMS)?;
+        require!(presale.has_rebates(), PresaleError::WrongSaleMode);
+
+        let amount_paid = self.user.quote_amounts[0];
+        require!(amount_paid > 0, PresaleError::NothingToSettle);
+
+        let rebate = presale.auction_rebate(self.user.buy_token_amount, amount_paid, self.usd_mint.decimals)?;
+
+        presale.quote_mints[0].reserved_amount = presale.quote_mints[0].reserved_amount
+            .checked_sub(amount_paid)
+            .ok_or(PresaleError::MathOverflow)?;
+        self.user.quote_amounts[0] = 0;
+        self.user.buy_quote_amount = self.user.buy_quote_amount
+            .checked_sub(rebate)
+            .ok_or(PresaleError::MathOverflow)?;
+
+        if rebate > 0 {
+            let binding = self.presale.admin.key();
+            let seeds = &[
+                &b"dogx_presale"[..],
+                binding.as_ref(),
+                &self.presale.seed.to_le_bytes(),
+                &[self.presale.bump],
+            ];
+            let signers_seeds = &[&seeds[..]];
+
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    self.token_program.to_account_info(),
+                    TransferChecked {
+                        from: self.vault_usd.to_account_info(),
+                        mint: self.usd_mint.to_account_info(),
+                        to: self.buyer_ata.to_account_info(),
+                        authority: self.presale.to_account_info(),
+                    },
+                    signers_seeds,
+                ),
+                rebate,
+                self.usd_mint.decimals
+            )?;
+        }
+
+        emit!(RebateClaimed {
+            presale: self.presale.key(),
+            buyer: self.buyer.key(),
+            clearing_price: self.presale.clearing_price,
+            amount_paid,
+            rebate,
+        });
+
+        // Tokens already claimed in full, return the rent to the buyer like `claim_token` does
+        if self.user.claim_amount == self.user.buy_token_amount {
+            self.user.has_claimed_token = true;
+            self.user.close(self.buyer.to_account_info())?;
+        }
+
+        Ok(())
+    }
+}
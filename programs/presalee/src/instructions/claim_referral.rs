@@ -0,0 +1,124 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{associated_token::AssociatedToken, token_interface::{
+        transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked
+    },
+};
+
+use crate::{errors::PresaleError, events::ReferralClaimed, state::{Presale, Referral, PAUSE_CLAIMS}};
+
+#[derive(Accounts)]
+pub struct ClaimReferral<'info> {
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+
+    // Sale token for bonus tokens, or the quote mint the commission was earned in
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        has_one = presale,
+        has_one = referrer,
+        seeds = [b"referral", presale.key().as_ref(), referrer.key().as_ref()],
+        bump = referral.bump
+    )]
+    pub referral: Account<'info, Referral>,
+
+    #[account(
+        init_if_needed,
+        payer = referrer,
+        associated_token::mint = mint,
+        associated_token::authority = referrer,
+        associated_token::token_program = token_program
+    )]
+    pub referrer_ata: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = presale,
+        associated_token::token_program = token_program
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> ClaimReferral<'info> {
+    // Pays out the commission earned in `mint` once the sale has succeeded, bonus tokens unlock
+    // on the buyers' vesting schedule
+    pub fn claim_referral(&mut self) -> Result<()> {
+        let presale = &mut self.presale;
+        let current_time = Clock::get()?.unix_timestamp as u64;
+        presale.require_succeeded(current_time)?;
+        presale.require_not_paused(PAUSE_CLAIMS)?;
+
+        let referral = &mut self.referral;
+        let amount = if self.mint.key() == presale.token_mint_address {
+            let amount = presale
+                .vested_amount(referral.token_commission, current_time)?
+                .checked_sub(referral.claimed_tokens)
+                .ok_or(PresaleError::MathOverflow)?;
+            referral.claimed_tokens = referral.claimed_tokens
+                .checked_add(amount)
+                .ok_or(PresaleError::MathOverflow)?;
+            presale.pay_owed_tokens(amount)?;
+            amount
+        } else {
+            let quote_index = presale.quote_index(&self.mint.key())?;
+            // Uniform-price auction commissions are credited now that the clearing price is set
+            if quote_index == 0 {
+                presale.settle_deferred_commission(referral)?;
+            }
+            let amount = referral.quote_commissions[quote_index];
+            referral.quote_commissions[quote_index] = 0;
+            let quote = &mut presale.quote_mints[quote_index];
+            quote.reserved_amount = quote.reserved_amount
+                .checked_sub(amount)
+                .ok_or(PresaleError::MathOverflow)?;
+            amount
+        };
+        require!(amount > 0, PresaleError::NothingToClaim);
+
+        let binding = self.presale.admin.key();
+        let seeds = &[
+            &b"dogx_presale"[..],
+            binding.as_ref(),
+            &self.presale.seed.to_le_bytes(),
+            &[self.presale.bump],
+        ];
+        let signers_seeds = &[&seeds[..]];
+
+        transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                TransferChecked {
+                    from: self.vault.to_account_info(),
+                    mint: self.mint.to_account_info(),
+                    to: self.referrer_ata.to_account_info(),
+                    authority: self.presale.to_account_info(),
+                },
+                signers_seeds,
+            ),
+            amount,
+            self.mint.decimals
+        )?;
+
+        emit!(ReferralClaimed {
+            presale: self.presale.key(),
+            referrer: self.referrer.key(),
+            mint: self.mint.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+}
@@ -1,10 +1,10 @@
 use anchor_lang::prelude::*;
-use anchor_spl::{associated_token::AssociatedToken, token::{
-        Mint, TokenAccount, Token, TransferChecked, transfer_checked
+use anchor_spl::{associated_token::AssociatedToken, token_interface::{
+        transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked
     },
 };
 
-use crate::{errors::PresaleError, state::{Presale, UserInfo}};
+use crate::{errors::PresaleError, events::RefundClaimed, state::{Presale, UserInfo, PAUSE_REFUNDS}};
 
 #[derive(Accounts)]
  pub struct ClaimRefund<'info> {
@@ -13,13 +13,14 @@ use crate::{errors::PresaleError, state::{Presale, UserInfo}};
     /// CHECK : FY
     pub admin: AccountInfo<'info>,
 
-    pub token_mint_address: Account<'info, Mint>,
-    pub usd_mint: Account<'info, Mint>,
+    pub token_mint_address: InterfaceAccount<'info, Mint>,
+    // Quote mint being refunded, any currency the buyer paid in
+    #[account(mint::token_program = token_program)]
+    pub usd_mint: InterfaceAccount<'info, Mint>,
 
     #[account(
         mut,
         has_one = token_mint_address,
-        has_one = usd_mint,
         has_one = admin,
         seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
         bump = presale.bump,
@@ -29,15 +30,17 @@ use crate::{errors::PresaleError, state::{Presale, UserInfo}};
     #[account(
         mut,
         associated_token::mint = usd_mint,
-        associated_token::authority = buyer
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
     )]
-    pub buyer_ata: Account<'info, TokenAccount>,
+    pub buyer_ata: InterfaceAccount<'info, TokenAccount>,
      #[account(
         mut,
         associated_token::mint = usd_mint,
-        associated_token::authority = presale
+        associated_token::authority = presale,
+        associated_token::token_program = token_program
     )]
-    pub vault_usd: Account<'info, TokenAccount>,
+    pub vault_usd: InterfaceAccount<'info, TokenAccount>,
     #[account(
         init_if_needed,
         payer = buyer,
@@ -49,7 +52,7 @@ use crate::{errors::PresaleError, state::{Presale, UserInfo}};
 
     pub system_program: Program<'info, System>,
     pub associated_token_program: Program<'info, AssociatedToken>,
-    pub token_program: Program<'info, Token>
+    pub token_program: Interface<'info, TokenInterface>
 
  }
 
@@ -58,22 +61,24 @@ use crate::{errors::PresaleError, state::{Presale, UserInfo}};
         let presale = &mut self.presale;
         let clock = Clock::get()?;
         let current_time = clock.unix_timestamp as u64;
-        let is_ended = !presale.is_live || current_time >= presale.end_time;
-        require!(is_ended, PresaleError::PresaleNotEnded);
-
-        require!(
-            presale.sold_token_amount < presale.softcap_amount,
-            PresaleError::SoftCapReached
-        );
+        // Batch auctions also pay back what settled bids did not spend once they succeed
+        let batch_refund = presale.is_batch_auction() && presale.require_succeeded(current_time).is_ok();
+        if !batch_refund {
+            presale.require_refundable(current_time)?;
+        }
+        presale.require_not_paused(PAUSE_REFUNDS)?;
 
         require!(!self.user.has_claimed_refund, PresaleError::AlreadyClaimed);
 
-        let refund_amount = self.user.buy_token_amount;
+        // Refunds are paid per currency, in whatever the buyer paid with
+        let quote_index = presale.quote_index(&self.usd_mint.key())?;
+        let refund_amount = self.user.quote_amounts[quote_index];
+        require!(refund_amount > 0, PresaleError::NothingToRefund);
 
         let binding = self.presale.admin.key();
         let seeds = &[
             &b"dogx_presale"[..],
-            &binding.as_ref(),
+            binding.as_ref(),
             &self.presale.seed.to_le_bytes(),
             &[self.presale.bump],
         ];
@@ -91,10 +96,24 @@ use crate::{errors::PresaleError, state::{Presale, UserInfo}};
             signers_seeds,
         ),
         refund_amount,
-        self.token_mint_address.decimals
+        self.usd_mint.decimals
     )?;
 
-        self.user.has_claimed_refund = true;
+        self.user.quote_amounts[quote_index] = 0;
+        if self.presale.is_batch_auction() {
+            let quote = &mut self.presale.quote_mints[quote_index];
+            quote.reserved_amount = quote.reserved_amount
+                .checked_sub(refund_amount)
+                .ok_or(PresaleError::MathOverflow)?;
+        }
+        self.user.has_claimed_refund = !self.user.has_pending_refund();
+
+        emit!(RefundClaimed {
+            presale: self.presale.key(),
+            buyer: self.buyer.key(),
+            quote_mint: self.usd_mint.key(),
+            amount: refund_amount,
+        });
         Ok(())
     }
  }
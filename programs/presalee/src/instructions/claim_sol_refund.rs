@@ -0,0 +1,78 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{transfer, Transfer};
+
+use crate::{errors::PresaleError, events::SolRefundClaimed, state::{Presale, UserInfo, PAUSE_REFUNDS}};
+
+#[derive(Accounts)]
+pub struct ClaimSolRefund<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        seeds = [b"sol_vault", presale.key().as_ref()],
+        bump
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"user", presale.key().as_ref(), buyer.key().as_ref() ],
+        bump = user.bump,
+    )]
+    pub user: Account<'info, UserInfo>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> ClaimSolRefund<'info> {
+    pub fn claim_sol_refund(&mut self, bumps: &ClaimSolRefundBumps) -> Result<()> {
+        let presale = &mut self.presale;
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp as u64;
+        presale.require_refundable(current_time)?;
+        presale.require_not_paused(PAUSE_REFUNDS)?;
+
+        require!(!self.user.has_claimed_refund, PresaleError::AlreadyClaimed);
+
+        let refund_amount = self.user.sol_amount;
+        require!(refund_amount > 0, PresaleError::NothingToRefund);
+
+        let presale_key = self.presale.key();
+        let seeds = &[
+            &b"sol_vault"[..],
+            presale_key.as_ref(),
+            &[bumps.sol_vault],
+        ];
+        let signers_seeds = &[&seeds[..]];
+
+        transfer(
+            CpiContext::new_with_signer(
+                self.system_program.to_account_info(),
+                Transfer {
+                    from: self.sol_vault.to_account_info(),
+                    to: self.buyer.to_account_info(),
+                },
+                signers_seeds,
+            ),
+            refund_amount,
+        )?;
+
+        self.user.sol_amount = 0;
+        self.user.has_claimed_refund = !self.user.has_pending_refund();
+
+        emit!(SolRefundClaimed {
+            presale: self.presale.key(),
+            buyer: self.buyer.key(),
+            lamports: refund_amount,
+        });
+        Ok(())
+    }
+}
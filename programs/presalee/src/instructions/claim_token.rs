@@ -1,36 +1,40 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked},
+    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
 };
 
 use crate::{
     errors::PresaleError,
-    state::{Presale, UserInfo},
+    events::TokensClaimed,
+    state::{Presale, UserInfo, PAUSE_CLAIMS},
 };
 
 #[derive(Accounts)]
 pub struct ClaimToken<'info> {
     #[account(mut)]
     pub buyer: Signer<'info>,
-    pub usd_mint: Account<'info, Mint>,
-    pub token_mint_address: Account<'info, Mint>,
+    pub usd_mint: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program)]
+    pub token_mint_address: InterfaceAccount<'info, Mint>,
     
 
     #[account(
         init_if_needed,
         payer = buyer,
         associated_token::mint = token_mint_address,
-        associated_token::authority = buyer
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
     )]
-    pub buyer_ata: Account<'info, TokenAccount>,
+    pub buyer_ata: InterfaceAccount<'info, TokenAccount>,
     #[account(
         init_if_needed,
         payer = buyer,
         associated_token::mint = token_mint_address,
-        associated_token::authority = presale
+        associated_token::authority = presale,
+        associated_token::token_program = token_program
     )]
-    pub vault_dog: Account<'info, TokenAccount>,
+    pub vault_dog: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
@@ -45,11 +49,10 @@ pub struct ClaimToken<'info> {
         mut,
         seeds = [b"user", presale.key().as_ref(), buyer.key().as_ref() ],
         bump = user.bump,
-        close = buyer
     )]
     pub user: Account<'info, UserInfo>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
@@ -58,15 +61,13 @@ impl<'info> ClaimToken<'info> {
     pub fn claim_token(&mut self,) -> Result<()> {
         let presale = &mut self.presale;
 
-        // let is_ended = !presale.is_live;
         let clock = Clock::get()?;
         let current_time = clock.unix_timestamp as u64;
-        let is_ended = !presale.is_live || current_time >= presale.end_time;
 
         // Debug logging
         msg!(
-            "Presale state: is_live={}, end_time={}, current_time={}",
-            presale.is_live,
+            "Presale state: status={:?}, end_time={}, current_time={}",
+            presale.status_at(current_time),
             presale.end_time,
             current_time
         );
@@ -76,22 +77,23 @@ impl<'info> ClaimToken<'info> {
             presale.softcap_amount
         );
 
-        require!(is_ended, PresaleError::PresaleNotEnded);
+        presale.require_succeeded(current_time)?;
+        presale.require_not_paused(PAUSE_CLAIMS)?;
 
-        // require!(
-        //     presale.sold_token_amount >= presale.softcap_amount,
-        //     PresaleError::SoftCapNotReached
-        // );
+        require!(!self.user.has_claimed_token, PresaleError::AlreadyClaimed);
 
-        // let amount = self.user.claim_amount;
-        let amount = self.user.buy_token_amount;
+        // Release only what has vested and not been claimed yet
+        let vested_amount = presale.vested_amount(self.user.buy_token_amount, current_time)?;
+        let amount = vested_amount
+            .checked_sub(self.user.claim_amount)
+            .ok_or(PresaleError::MathOverflow)?;
 
-        require!(!self.user.has_claimed_token, PresaleError::AlreadyClaimed);
+        require!(amount > 0, PresaleError::NothingToClaim);
 
         let binding = self.presale.admin.key();
         let seeds = &[
             &b"dogx_presale"[..],
-            &binding.as_ref(),
+            binding.as_ref(),
             &self.presale.seed.to_le_bytes(),
             &[self.presale.bump],
         ];
@@ -112,8 +114,27 @@ impl<'info> ClaimToken<'info> {
         self.token_mint_address.decimals
     )?;
 
-    self.user.has_claimed_token = true;
-
+        self.user.claim_amount = self.user.claim_amount
+            .checked_add(amount)
+            .ok_or(PresaleError::MathOverflow)?;
+        self.user.claim_time = current_time;
+        self.presale.pay_owed_tokens(amount)?;
+
+        emit!(TokensClaimed {
+            presale: self.presale.key(),
+            buyer: self.buyer.key(),
+            amount,
+            total_claimed: self.user.claim_amount,
+        });
+
+        // Fully vested and claimed, return the rent to the buyer unless a rebate or the unspent
+        // part of a bid is still owed
+        let quote_pending = (self.presale.has_rebates() || self.presale.is_batch_auction())
+            && self.user.quote_amounts[0] > 0;
+        if self.user.claim_amount == self.user.buy_token_amount && !quote_pending {
+            self.user.has_claimed_token = true;
+            self.user.close(self.buyer.to_account_info())?;
+        }
 
         Ok(())
     }
@@ -1,6 +1,6 @@
 use anchor_lang::prelude::*;
 
-use crate::state::Presale;
+use crate::{errors::PresaleError, events::PresaleClosed, state::Presale};
 
 #[derive(Accounts)]
 pub struct ClosePresale<'info> {
@@ -11,6 +11,7 @@ pub struct ClosePresale<'info> {
         mut,
         seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
         bump = presale.bump,
+        constraint = presale.authority == admin.key() @ PresaleError::Unauthorized,
         close = admin
     )]
     pub presale: Account<'info, Presale>,
@@ -20,16 +21,14 @@ pub struct ClosePresale<'info> {
 
 impl<'info> ClosePresale<'info> {
     pub fn close_presale(&mut self) -> Result<()> {
-        // Ensure presale is ended
-        require!(!self.presale.is_live, ErrorCode::PresaleNotEnded);
+        let current_time = Clock::get()?.unix_timestamp as u64;
+        self.presale.require_closable(current_time)?;
+
+        emit!(PresaleClosed {
+            presale: self.presale.key(),
+        });
 
         // The presale account will be closed automatically due to `close = admin`
         Ok(())
     }
-}
-
-#[error_code]
-pub enum ErrorCode {
-    #[msg("Presale has not ended")]
-    PresaleNotEnded,
 }
\ No newline at end of file
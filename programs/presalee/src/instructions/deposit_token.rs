@@ -1,47 +1,54 @@
 use anchor_lang::prelude::*;
-use anchor_spl::{associated_token::AssociatedToken, token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked}};
+use anchor_spl::{associated_token::AssociatedToken, token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked}};
 
-use crate::state::Presale;
+use crate::{errors::PresaleError, events::TokensDeposited, state::Presale};
 
 #[derive(Accounts)]
 pub struct DepositToken<'info>{
     #[account(mut)]
     pub admin: Signer<'info>,
 
-    pub usd_mint: Account<'info, Mint>,
-    pub token_mint_address: Account<'info, Mint>,
+    pub usd_mint: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program)]
+    pub token_mint_address: InterfaceAccount<'info, Mint>,
 
     #[account(
         mut,
         associated_token::mint = token_mint_address,
-        associated_token::authority = admin
+        associated_token::authority = admin,
+        associated_token::token_program = token_program
     )]
-    pub admin_ata: Account<'info, TokenAccount>,
+    pub admin_ata: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         init_if_needed,
         payer = admin,
         associated_token::mint = token_mint_address,
-        associated_token::authority = presale
+        associated_token::authority = presale,
+        associated_token::token_program = token_program
     )]
-    pub vault_dog: Account<'info, TokenAccount>,
+    pub vault_dog: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
         has_one = token_mint_address,
         has_one = usd_mint,
-        seeds = [b"dogx_presale", admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        constraint = presale.authority == admin.key() @ PresaleError::Unauthorized,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
         bump = presale.bump
     )]
     pub presale: Account<'info, Presale>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>
 }
 
 impl <'info> DepositToken<'info> {
     pub fn deposit_token(&mut self, amount: u64) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp as u64;
+        require!(!self.presale.is_ended(current_time), PresaleError::PresaleEnded);
+
         let cpi_program =  self.token_program.to_account_info();
 
         let cpi_accounts = TransferChecked{
@@ -51,10 +58,25 @@ impl <'info> DepositToken<'info> {
             authority: self.admin.to_account_info() 
         };
 
+        let vault_balance_before = self.vault_dog.amount;
+
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
         transfer_checked(cpi_ctx, amount, self.token_mint_address.decimals)?;
 
-        self.presale.deposit_token_amount = amount;
+        // Token-2022 transfer fees are withheld from the vault, record what actually arrived
+        self.vault_dog.reload()?;
+        let amount_received = self.vault_dog.amount
+            .checked_sub(vault_balance_before)
+            .ok_or(PresaleError::MathOverflow)?;
+
+        // Nothing leaves the vault before the sale ends, so its balance is everything deposited
+        // across top-ups, e.g. tokens for a level added while live
+        self.presale.deposit_token_amount = self.vault_dog.amount;
+
+        emit!(TokensDeposited {
+            presale: self.presale.key(),
+            amount: amount_received,
+        });
         
         Ok(())
     }
@@ -1,6 +1,6 @@
 use anchor_lang::prelude::*;
 
-use crate::{errors::PresaleError, state:: Presale};
+use crate::{errors::PresaleError, events::PresaleEnded, state::{Presale, PresaleAction}};
 
 #[derive(Accounts)]
 pub struct EndPresale<'info>{
@@ -11,6 +11,7 @@ pub struct EndPresale<'info>{
         mut,
         seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
         bump = presale.bump,
+        constraint = presale.roles.operator == admin.key() @ PresaleError::Unauthorized,
     )]
     pub presale: Account<'info, Presale>,
 
@@ -22,10 +23,14 @@ impl<'info> EndPresale<'info>{
         let presale = &mut self.presale;
         let clock = Clock::get()?;
         let current_time = clock.unix_timestamp as u64;
-        //checks if presale is live
-        require!(presale.is_live || current_time >= presale.end_time, PresaleError::PresaleEnded);
-        //end presale
-        self.presale.is_live = false;
+        //end presale, vesting starts from the actual end
+        presale.transition(PresaleAction::End, current_time)?;
+
+        emit!(PresaleEnded {
+            presale: presale.key(),
+            end_time: presale.end_time,
+            sold_token_amount: presale.sold_token_amount,
+        });
         Ok(())
     }
 }
\ No newline at end of file
@@ -1,22 +1,24 @@
 use anchor_lang::prelude::*;
-use anchor_spl::{associated_token::AssociatedToken, token::{Mint, Token, TokenAccount}};
+use anchor_spl::{associated_token::AssociatedToken, token_interface::{Mint, TokenAccount, TokenInterface}};
 
-use crate::state::{Level, Presale};
+use crate::{errors::PresaleError, events::PresaleCreated, state::{Level, LevelConfig, Presale, PresaleRoles, PresaleStatus, PurchaseLimits, QuoteMint, SaleMode, VestingSchedule}};
 
 #[derive(Accounts)]
-#[instruction(seed: u64)]
+#[instruction(seed: u64, token_mint_address: Pubkey, usd_mint: Pubkey, softcap_amount: u64, hardcap_amount: u64, deposit_token_amount: u64, levels: Vec<LevelConfig>)]
 pub struct InitPresale<'info>{
     #[account(mut)]
     pub admin: Signer<'info>,
 
-    pub token_mint_address: Account<'info, Mint>,
-    pub usd_mint: Account<'info, Mint>,
+    #[account(mint::token_program = token_program)]
+    pub token_mint_address: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = quote_token_program)]
+    pub usd_mint: InterfaceAccount<'info, Mint>,
 
     #[account(
         init,
         payer = admin,
         seeds = [b"dogx_presale", admin.key().as_ref(), seed.to_le_bytes().as_ref()],
-        space = 8 + Presale::INIT_SPACE,
+        space = Presale::space(levels.len()),
         bump,
     )]
     pub presale: Account<'info, Presale>,
@@ -25,20 +27,25 @@ pub struct InitPresale<'info>{
         init,
         payer = admin,
         associated_token::mint = token_mint_address,
-        associated_token::authority = presale
+        associated_token::authority = presale,
+        associated_token::token_program = token_program
     )]
-    pub vault_dog: Account<'info, TokenAccount>,
+    pub vault_dog: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         init,
         payer = admin,
         associated_token::mint = usd_mint,
-        associated_token::authority = presale
+        associated_token::authority = presale,
+        associated_token::token_program = quote_token_program
     )]
-    pub vault_usd: Account<'info, TokenAccount>,
+    pub vault_usd: InterfaceAccount<'info, TokenAccount>,
 
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
+    // Token program of the presale token mint
+    pub token_program: Interface<'info, TokenInterface>,
+    // Token program of the quote mint, may differ from the presale token's
+    pub quote_token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
@@ -51,30 +58,72 @@ impl<'info> InitPresale<'info>{
     softcap_amount: u64,
     hardcap_amount: u64,
     deposit_token_amount: u64,
-    levels: [Level; 7], 
+    levels: Vec<LevelConfig>,
     sold_token_amount: u64,
     start_time: u64,
     end_time: u64,
+    auto_start: bool,
+    vesting: VestingSchedule,
+    limits: PurchaseLimits,
+    sale_mode: SaleMode,
     bumps: &InitPresaleBumps
 ) -> Result<()>{
+    require!(token_mint_address == self.token_mint_address.key(), PresaleError::TokenMintMismatch);
+    require!(usd_mint == self.usd_mint.key(), PresaleError::UsdMintMismatch);
+    require!(sold_token_amount == 0, PresaleError::SoldAmountNotZero);
+
+    // A scheduled sale opens by itself at `start_time`, otherwise the operator starts it
+    let status = if auto_start { PresaleStatus::Scheduled } else { PresaleStatus::Draft };
+
     self.presale.set_inner(Presale {
         seed, 
         admin: self.admin.key(),
+        authority: self.admin.key(),
+        pending_authority: None,
+        roles: PresaleRoles::new(self.admin.key()),
         token_mint_address,
         usd_mint,
+        token_decimals: self.token_mint_address.decimals,
+        quote_mints: vec![QuoteMint::new(usd_mint, self.usd_mint.decimals)],
         current_level: 0,
         softcap_amount,
         hardcap_amount,
-        deposit_token_amount,
+        // The vault is empty until `deposit_token`, the sale cannot start before it is funded
+        deposit_token_amount: 0,
         sold_token_amount,
         start_time,
         end_time,
-        levels,
-        is_live: false,
-        is_soft_capped: false,
-        is_hard_capped: false,
+        clearing_price: 0,
+        levels: levels.into_iter().map(Level::from).collect(),
+        status,
+        pause_flags: 0,
+        paused_at: 0,
+        extend_on_resume: false,
+        vesting,
+        sol_payment: None,
+        sol_raised_amount: 0,
+        limits,
+        whitelist_root: None,
+        referral: None,
+        referral_token_amount: 0,
+        owed_token_amount: 0,
+        sale_mode,
         bump: bumps.presale,
     });
+    self.presale.validate(Clock::get()?.unix_timestamp as u64)?;
+    require!(self.presale.tokens_for_sale()? == deposit_token_amount, PresaleError::LevelTotalMismatch);
+
+        emit!(PresaleCreated {
+            presale: self.presale.key(),
+            admin: self.admin.key(),
+            token_mint_address,
+            usd_mint,
+            softcap_amount,
+            hardcap_amount,
+            start_time,
+            end_time,
+            auto_start,
+        });
         
         Ok(())
     }
@@ -1,6 +1,6 @@
 use anchor_lang::prelude::*;
 
-use crate::state::{Presale, UserInfo};
+use crate::state::{Presale, UserInfo, MAX_QUOTE_MINTS};
 
 
 #[derive(Accounts)]
@@ -15,8 +15,9 @@ pub struct InitUser<'info>{
     )]
     pub presale: Account<'info, Presale>,
 
+    // Created once, a buyer cannot reset their own totals
     #[account(
-        init_if_needed,
+        init,
         payer = buyer,
         seeds = [b"user",  presale.key().as_ref(), buyer.key().as_ref()],
         space = 8 + UserInfo::INIT_SPACE,
@@ -28,16 +29,18 @@ pub struct InitUser<'info>{
 }
 
 impl<'info> InitUser<'info>{
-    pub fn init_user(&mut self, buy_quote_amount: u64, buy_token_amount: u64, buy_time: u64, claim_amount: u64, claim_time: u64, bumps: &InitUserBumps) -> Result<()>{
+    pub fn init_user(&mut self, bumps: &InitUserBumps) -> Result<()>{
         self.user_info.set_inner(UserInfo {
             buyer: self.buyer.key(), 
-            buy_quote_amount,
-            buy_token_amount,
+            buy_quote_amount: 0,
+            quote_amounts: [0; MAX_QUOTE_MINTS],
+            sol_amount: 0,
+            buy_token_amount: 0,
             has_claimed_token: false,
             has_claimed_refund: false,
-            buy_time,
-            claim_amount,
-            claim_time,
+            buy_time: 0,
+            claim_amount: 0,
+            claim_time: 0,
             bump: bumps.user_info 
         });
         
@@ -9,6 +9,29 @@ pub mod withdraw_token;
 pub mod end_presale;
 pub mod claim_refund;
 pub mod close_presale;
+pub mod cancel_presale;
+pub mod pause_presale;
+pub mod resume_presale;
+pub mod set_whitelist;
+pub mod add_quote_mint;
+pub mod update_quote_mint;
+pub mod set_sol_price_feed;
+pub mod buy_token_sol;
+pub mod claim_sol_refund;
+pub mod withdraw_sol;
+pub mod propose_admin;
+pub mod accept_admin;
+pub mod set_role;
+pub mod update_presale;
+pub mod settle_commitment;
+pub mod claim_rebate;
+pub mod place_bid;
+pub mod cancel_bid;
+pub mod settle_auction;
+pub mod settle_bid;
+pub mod set_referral_config;
+pub mod register_referrer;
+pub mod claim_referral;
 
 pub use init_presale::*;
 pub use deposit_token::*;
@@ -20,4 +43,27 @@ pub use withdraw_usd::*;
 pub use withdraw_token::*;
 pub use end_presale::*;
 pub use claim_refund::*;
-pub use close_presale::*;
\ No newline at end of file
+pub use close_presale::*;
+pub use cancel_presale::*;
+pub use pause_presale::*;
+pub use resume_presale::*;
+pub use set_whitelist::*;
+pub use add_quote_mint::*;
+pub use update_quote_mint::*;
+pub use set_sol_price_feed::*;
+pub use buy_token_sol::*;
+pub use claim_sol_refund::*;
+pub use withdraw_sol::*;
+pub use propose_admin::*;
+pub use accept_admin::*;
+pub use set_role::*;
+pub use update_presale::*;
+pub use settle_commitment::*;
+pub use claim_rebate::*;
+pub use place_bid::*;
+pub use cancel_bid::*;
+pub use settle_auction::*;
+pub use settle_bid::*;
+pub use set_referral_config::*;
+pub use register_referrer::*;
+pub use claim_referral::*;
\ No newline at end of file
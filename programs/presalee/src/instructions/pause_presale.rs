@@ -0,0 +1,46 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::PresaleError,
+    events::PresalePaused,
+    state::{Presale, PresaleAction, PresaleStatus, PAUSE_ALL, PAUSE_BUYS},
+};
+
+#[derive(Accounts)]
+pub struct PausePresale<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+        constraint = presale.roles.can_pause(&admin.key()) @ PresaleError::Unauthorized,
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+impl<'info> PausePresale<'info> {
+    pub fn pause_presale(&mut self, mask: u8, extend_end_time: bool) -> Result<()> {
+        let presale = &mut self.presale;
+        let current_time = Clock::get()?.unix_timestamp as u64;
+
+        require!(mask != 0 && mask & !PAUSE_ALL == 0, PresaleError::InvalidPauseMask);
+
+        // Halting buys on a running sale also stops its clock
+        if mask & PAUSE_BUYS != 0 && presale.sync_status(current_time) == PresaleStatus::Live {
+            presale.transition(PresaleAction::Pause, current_time)?;
+            presale.paused_at = current_time;
+            presale.extend_on_resume = extend_end_time;
+        }
+        presale.pause_flags |= mask;
+
+        emit!(PresalePaused {
+            presale: presale.key(),
+            pause_flags: presale.pause_flags,
+            paused_at: current_time,
+        });
+
+        Ok(())
+    }
+}
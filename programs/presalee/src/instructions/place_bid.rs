@@ -0,0 +1,151 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{associated_token::AssociatedToken, token_interface::{
+        transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked
+    },
+};
+
+use crate::{errors::PresaleError, events::BidPlaced, state::{Bid, Presale}, utils::amount_with_transfer_fee};
+
+#[derive(Accounts)]
+pub struct PlaceBid<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    // Primary quote mint, the only one batch auctions take
+    #[account(mint::token_program = token_program)]
+    pub usd_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        has_one = usd_mint,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        associated_token::mint = usd_mint,
+        associated_token::authority = bidder,
+        associated_token::token_program = token_program
+    )]
+    pub bidder_ata: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        associated_token::mint = usd_mint,
+        associated_token::authority = presale,
+        associated_token::token_program = token_program
+    )]
+    pub vault_usd: InterfaceAccount<'info, TokenAccount>,
+    // One bid per buyer, cancel it to bid again
+    #[account(
+        init,
+        payer = bidder,
+        space = 8 + Bid::INIT_SPACE,
+        seeds = [b"bid", presale.key().as_ref(), bidder.key().as_ref()],
+        bump
+    )]
+    pub bid: Account<'info, Bid>,
+
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>
+}
+
+impl<'info> PlaceBid<'info> {
+    // Bids for `quantity` tokens at the price of tick `level` or lower. The full amount at the
+    // tick price is locked now, `settle_bid` keeps only what the clearing price costs.
+    pub fn place_bid(
+        &mut self,
+        level: u8,
+        quantity: u64,
+        deadline: u64,
+        max_allocation: u64,
+        proof: Vec<[u8; 32]>,
+        bumps: &PlaceBidBumps,
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp as u64;
+
+        require!(self.presale.is_batch_auction(), PresaleError::WrongSaleMode);
+        self.presale.require_live(current_time)?;
+        require!(current_time <= deadline, PresaleError::DeadlineExceeded);
+        require!(self.presale.quote_mints[0].enabled, PresaleError::QuoteMintDisabled);
+        self.presale.check_whitelist(&self.bidder.key(), max_allocation, &proof)?;
+
+        let price = self.presale.levels
+            .get(level as usize)
+            .map(|tick| tick.price)
+            .ok_or(PresaleError::InvalidBidLevel)?;
+        require!(quantity > 0, PresaleError::NoTokensAvailable);
+        let deposit = self.presale.quote_for_tokens(quantity, price, self.usd_mint.decimals)?;
+
+        // A bid is the wallet's only order, so it is checked as its whole position
+        if self.presale.whitelist_root.is_some() {
+            require!(deposit <= max_allocation, PresaleError::AllocationExceeded);
+        }
+        self.presale.limits.check(deposit, deposit, quantity)?;
+
+        // Bidder covers any Token-2022 transfer fee so the vault is credited the full deposit
+        let vault_balance_before = self.vault_usd.amount;
+        let transfer_amount = amount_with_transfer_fee(&self.usd_mint.to_account_info(), deposit)?;
+
+        transfer_checked(
+            CpiContext::new(
+                self.token_program.to_account_info(),
+                TransferChecked {
+                    from: self.bidder_ata.to_account_info(),
+                    mint: self.usd_mint.to_account_info(),
+                    to: self.vault_usd.to_account_info(),
+                    authority: self.bidder.to_account_info(),
+                },
+            ),
+            transfer_amount,
+            self.usd_mint.decimals
+        )?;
+
+        self.vault_usd.reload()?;
+        let amount_received = self.vault_usd.amount
+            .checked_sub(vault_balance_before)
+            .ok_or(PresaleError::MathOverflow)?;
+        require!(amount_received >= deposit, PresaleError::TransferFeeMismatch);
+
+        // Deposits stay reserved until the bid is settled against the clearing price
+        let quote = &mut self.presale.quote_mints[0];
+        quote.raised_amount = quote.raised_amount
+            .checked_add(deposit)
+            .ok_or(PresaleError::MathOverflow)?;
+        quote.reserved_amount = quote.reserved_amount
+            .checked_add(deposit)
+            .ok_or(PresaleError::MathOverflow)?;
+        let value = self.presale.token_value(quantity, price)?;
+        let tick = &mut self.presale.levels[level as usize];
+        tick.tokens_sold = tick.tokens_sold
+            .checked_add(quantity)
+            .ok_or(PresaleError::MathOverflow)?;
+        tick.raised = tick.raised
+            .checked_add(value)
+            .ok_or(PresaleError::MathOverflow)?;
+
+        self.bid.set_inner(Bid {
+            bidder: self.bidder.key(),
+            level,
+            quantity,
+            deposit,
+            bid_time: current_time,
+            bump: bumps.bid,
+        });
+
+        emit!(BidPlaced {
+            presale: self.presale.key(),
+            bidder: self.bidder.key(),
+            level,
+            price,
+            quantity,
+            deposit,
+            timestamp: current_time,
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::PresaleError, events::AdminProposed, state::Presale};
+
+#[derive(Accounts)]
+pub struct ProposeAdmin<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+        constraint = presale.authority == admin.key() @ PresaleError::Unauthorized,
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+impl<'info> ProposeAdmin<'info> {
+    pub fn propose_admin(&mut self, new_admin: Pubkey) -> Result<()> {
+        // Overwrites any earlier proposal, proposing the current authority cancels it
+        self.presale.pending_authority = if new_admin == self.presale.authority {
+            None
+        } else {
+            Some(new_admin)
+        };
+
+        emit!(AdminProposed {
+            presale: self.presale.key(),
+            authority: self.presale.authority,
+            pending_authority: new_admin,
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::PresaleError, events::ReferrerRegistered, state::{Presale, Referral, MAX_QUOTE_MINTS}};
+
+#[derive(Accounts)]
+pub struct RegisterReferrer<'info> {
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+
+    #[account(
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init,
+        payer = referrer,
+        space = 8 + Referral::INIT_SPACE,
+        seeds = [b"referral", presale.key().as_ref(), referrer.key().as_ref()],
+        bump
+    )]
+    pub referral: Account<'info, Referral>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> RegisterReferrer<'info> {
+    // Opens the account buyers name as their referrer, anyone may register
+    pub fn register_referrer(&mut self, bumps: &RegisterReferrerBumps) -> Result<()> {
+        require!(self.presale.referral.is_some(), PresaleError::ReferralsDisabled);
+
+        self.referral.set_inner(Referral {
+            presale: self.presale.key(),
+            referrer: self.referrer.key(),
+            referred_volume: 0,
+            token_commission: 0,
+            quote_commissions: [0; MAX_QUOTE_MINTS],
+            quote_commission_value: 0,
+            claimed_tokens: 0,
+            deferred_commission: 0,
+            deferred_reserve: 0,
+            bump: bumps.referral,
+        });
+
+        emit!(ReferrerRegistered {
+            presale: self.presale.key(),
+            referrer: self.referrer.key(),
+        });
+
+        Ok(())
+    }
+}
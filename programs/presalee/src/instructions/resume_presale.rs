@@ -0,0 +1,52 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::PresaleError,
+    events::PresaleResumed,
+    state::{Presale, PresaleAction, PresaleStatus, PAUSE_ALL, PAUSE_BUYS},
+};
+
+#[derive(Accounts)]
+pub struct ResumePresale<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+        constraint = presale.roles.operator == admin.key() @ PresaleError::Unauthorized,
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+impl<'info> ResumePresale<'info> {
+    pub fn resume_presale(&mut self, mask: u8) -> Result<()> {
+        let presale = &mut self.presale;
+        let current_time = Clock::get()?.unix_timestamp as u64;
+
+        require!(mask != 0 && mask & !PAUSE_ALL == 0, PresaleError::InvalidPauseMask);
+
+        presale.pause_flags &= !mask;
+
+        if mask & PAUSE_BUYS != 0 && presale.sync_status(current_time) == PresaleStatus::Paused {
+            // Give buyers back the time the sale was halted
+            if presale.extend_on_resume {
+                let paused_duration = current_time.saturating_sub(presale.paused_at);
+                presale.end_time = presale.end_time
+                    .checked_add(paused_duration)
+                    .ok_or(PresaleError::MathOverflow)?;
+                presale.extend_on_resume = false;
+            }
+            presale.transition(PresaleAction::Resume, current_time)?;
+        }
+
+        emit!(PresaleResumed {
+            presale: presale.key(),
+            pause_flags: presale.pause_flags,
+            end_time: presale.end_time,
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::PresaleError, state::{Presale, ReferralConfig}};
+
+#[derive(Accounts)]
+pub struct SetReferralConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+        constraint = presale.authority == admin.key() @ PresaleError::Unauthorized,
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+impl<'info> SetReferralConfig<'info> {
+    pub fn set_referral_config(&mut self, referral: Option<ReferralConfig>) -> Result<()> {
+        if let Some(config) = referral {
+            require!(config.is_valid(), PresaleError::InvalidReferralConfig);
+        }
+
+        // Applies to later purchases, `None` stops crediting and commissions already earned stay claimable
+        self.presale.referral = referral;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::PresaleError, events::RoleUpdated, state::{Presale, PresaleRole}};
+
+#[derive(Accounts)]
+pub struct SetRole<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+        constraint = presale.authority == admin.key() @ PresaleError::Unauthorized,
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+impl<'info> SetRole<'info> {
+    pub fn set_role(&mut self, role: PresaleRole, key: Pubkey) -> Result<()> {
+        // Only the authority hands out roles, it can appoint itself to any of them
+        self.presale.roles.set(role, key);
+
+        emit!(RoleUpdated {
+            presale: self.presale.key(),
+            role,
+            key,
+        });
+
+        Ok(())
+    }
+}
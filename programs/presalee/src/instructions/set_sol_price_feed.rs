@@ -0,0 +1,56 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{transfer, Transfer};
+
+use crate::{errors::PresaleError, state::{Presale, SolPriceFeed}};
+
+#[derive(Accounts)]
+pub struct SetSolPriceFeed<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+        constraint = presale.authority == admin.key() @ PresaleError::Unauthorized,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        seeds = [b"sol_vault", presale.key().as_ref()],
+        bump
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> SetSolPriceFeed<'info> {
+    pub fn set_sol_price_feed(&mut self, sol_payment: Option<SolPriceFeed>) -> Result<()> {
+        // The vault holds no data but must stay rent exempt, so the admin funds the minimum
+        // before the first payment: buys of any size then go through and refunds never take it
+        // below the floor, only `withdraw_sol` empties it
+        if sol_payment.is_some() {
+            let rent_exempt = Rent::get()?.minimum_balance(0);
+            let top_up = rent_exempt.saturating_sub(self.sol_vault.lamports());
+            if top_up > 0 {
+                transfer(
+                    CpiContext::new(
+                        self.system_program.to_account_info(),
+                        Transfer {
+                            from: self.admin.to_account_info(),
+                            to: self.sol_vault.to_account_info(),
+                        },
+                    ),
+                    top_up,
+                )?;
+            }
+        }
+
+        // `None` stops accepting SOL, lamports already paid stay refundable and withdrawable
+        self.presale.sol_payment = sol_payment;
+
+        Ok(())
+    }
+}
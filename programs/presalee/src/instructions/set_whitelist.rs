@@ -0,0 +1,26 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::PresaleError, state::Presale};
+
+#[derive(Accounts)]
+pub struct SetWhitelist<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+        constraint = presale.authority == admin.key() @ PresaleError::Unauthorized,
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+impl<'info> SetWhitelist<'info> {
+    pub fn set_whitelist(&mut self, whitelist_root: Option<[u8; 32]>) -> Result<()> {
+        // `None` opens the presale to every buyer
+        self.presale.whitelist_root = whitelist_root;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+use anchor_lang::prelude::*;
+
+use crate::{events::AuctionSettled, state::Presale};
+
+#[derive(Accounts)]
+pub struct SettleAuction<'info> {
+    // Anyone may settle a finished auction
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+impl<'info> SettleAuction<'info> {
+    // Fixes the clearing price of a successful batch auction from the bids on its ticks, every
+    // winning bid is then settled at that price by `settle_bid`
+    pub fn settle_auction(&mut self) -> Result<()> {
+        let presale = &mut self.presale;
+        let current_time = Clock::get()?.unix_timestamp as u64;
+        presale.require_succeeded(current_time)?;
+        let total_demand = presale.settle_clearing_price()?;
+
+        emit!(AuctionSettled {
+            presale: presale.key(),
+            clearing_price: presale.clearing_price,
+            total_demand,
+            tokens_sold: presale.sold_token_amount,
+        });
+
+        Ok(())
+    }
+}
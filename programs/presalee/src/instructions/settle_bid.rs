@@ -0,0 +1,79 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+
+use crate::{errors::PresaleError, events::BidSettled, state::{Bid, Presale, UserInfo}};
+
+#[derive(Accounts)]
+pub struct SettleBid<'info> {
+    // Anyone may settle a bid, the outcome is always credited to the bidder
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: only used as the owner of the bid and user accounts, and receives the bid's rent
+    #[account(mut)]
+    pub bidder: UncheckedAccount<'info>,
+
+    // Primary quote mint, bids are priced in its units
+    pub usd_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        has_one = usd_mint,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        close = bidder,
+        has_one = bidder,
+        seeds = [b"bid", presale.key().as_ref(), bidder.key().as_ref()],
+        bump = bid.bump
+    )]
+    pub bid: Account<'info, Bid>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + UserInfo::INIT_SPACE,
+        seeds = [b"user", presale.key().as_ref(), bidder.key().as_ref() ],
+        bump
+    )]
+    pub user: Account<'info, UserInfo>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> SettleBid<'info> {
+    // Turns a bid into the bidder's purchase once the auction is over: tokens won at the clearing
+    // price are claimed through `claim_token`, the rest of the deposit through `claim_refund`.
+    // Bids in a failed or cancelled auction are refunded whole.
+    pub fn settle_bid(&mut self, bumps: &SettleBidBumps) -> Result<()> {
+        let presale = &mut self.presale;
+        let current_time = Clock::get()?.unix_timestamp as u64;
+
+        let (tokens, amount_spent, refund) = presale.bid_settlement(&self.bid, self.usd_mint.decimals, current_time)?;
+
+        // The refund stays reserved until it is claimed
+        presale.quote_mints[0].reserved_amount = presale.quote_mints[0].reserved_amount
+            .checked_sub(amount_spent)
+            .ok_or(PresaleError::MathOverflow)?;
+
+        presale.owe_tokens(tokens)?;
+
+        self.user.record_purchase(self.bidder.key(), amount_spent, tokens, self.bid.bid_time)?;
+        self.user.quote_amounts[0] = self.user.quote_amounts[0]
+            .checked_add(refund)
+            .ok_or(PresaleError::MathOverflow)?;
+        self.user.bump = bumps.user;
+
+        emit!(BidSettled {
+            presale: self.presale.key(),
+            bidder: self.bidder.key(),
+            tokens,
+            amount_spent,
+            refund,
+        });
+
+        Ok(())
+    }
+}
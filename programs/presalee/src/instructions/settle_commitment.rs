@@ -0,0 +1,116 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{associated_token::AssociatedToken, token_interface::{
+        transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked
+    },
+};
+
+use crate::{errors::PresaleError, events::CommitmentSettled, state::{Presale, UserInfo, PAUSE_CLAIMS}};
+
+#[derive(Accounts)]
+pub struct SettleCommitment<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    // Primary quote mint, the only one overflow sales take
+    #[account(mint::token_program = token_program)]
+    pub usd_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        has_one = usd_mint,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = usd_mint,
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
+    )]
+    pub buyer_ata: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = usd_mint,
+        associated_token::authority = presale,
+        associated_token::token_program = token_program
+    )]
+    pub vault_usd: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"user", presale.key().as_ref(), buyer.key().as_ref() ],
+        bump = user.bump,
+    )]
+    pub user: Account<'info, UserInfo>,
+
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> SettleCommitment<'info> {
+    // Turns an overflow commitment into its token allocation, claimed later through
+    // `claim_token`, and pays back the part of it the sale did not need
+    pub fn settle_commitment(&mut self) -> Result<()> {
+        let presale = &mut self.presale;
+        let current_time = Clock::get()?.unix_timestamp as u64;
+        presale.require_succeeded(current_time)?;
+        presale.require_not_paused(PAUSE_CLAIMS)?;
+
+        let commitment = self.user.quote_amounts[0];
+        require!(commitment > 0, PresaleError::NothingToSettle);
+
+        let (tokens, amount_spent) = presale.overflow_allocation(commitment)?;
+        let refund = commitment - amount_spent;
+
+        presale.quote_mints[0].reserved_amount = presale.quote_mints[0].reserved_amount
+            .checked_sub(commitment)
+            .ok_or(PresaleError::MathOverflow)?;
+        presale.owe_tokens(tokens)?;
+        self.user.quote_amounts[0] = 0;
+        self.user.buy_quote_amount = self.user.buy_quote_amount
+            .checked_sub(refund)
+            .ok_or(PresaleError::MathOverflow)?;
+        self.user.buy_token_amount = self.user.buy_token_amount
+            .checked_add(tokens)
+            .ok_or(PresaleError::MathOverflow)?;
+
+        if refund > 0 {
+            let binding = self.presale.admin.key();
+            let seeds = &[
+                &b"dogx_presale"[..],
+                binding.as_ref(),
+                &self.presale.seed.to_le_bytes(),
+                &[self.presale.bump],
+            ];
+            let signers_seeds = &[&seeds[..]];
+
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    self.token_program.to_account_info(),
+                    TransferChecked {
+                        from: self.vault_usd.to_account_info(),
+                        mint: self.usd_mint.to_account_info(),
+                        to: self.buyer_ata.to_account_info(),
+                        authority: self.presale.to_account_info(),
+                    },
+                    signers_seeds,
+                ),
+                refund,
+                self.usd_mint.decimals
+            )?;
+        }
+
+        emit!(CommitmentSettled {
+            presale: self.presale.key(),
+            buyer: self.buyer.key(),
+            tokens,
+            amount_spent,
+            refund,
+        });
+
+        Ok(())
+    }
+}
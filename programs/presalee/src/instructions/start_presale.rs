@@ -1,6 +1,6 @@
 use anchor_lang::prelude::*;
 
-use crate::{errors::PresaleError, state::Presale};
+use crate::{errors::PresaleError, events::PresaleStarted, state::{Presale, PresaleAction}};
 
 #[derive(Accounts)]
 pub struct StartPresale<'info> {
@@ -11,7 +11,7 @@ pub struct StartPresale<'info> {
         mut,
         seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
         bump = presale.bump,
-        has_one = admin,
+        constraint = presale.roles.operator == admin.key() @ PresaleError::Unauthorized,
     )]
     pub presale: Account<'info, Presale>,
 }
@@ -21,12 +21,16 @@ impl<'info> StartPresale<'info> {
         let current_time = Clock::get()?.unix_timestamp as u64;
         
         // Validations
-        require!(!self.presale.is_live, PresaleError::AlreadyLive);
         require!(current_time < self.presale.end_time, PresaleError::PresaleEnded);
         
         // Start the presale
-        self.presale.is_live = true;
+        self.presale.transition(PresaleAction::Start, current_time)?;
         self.presale.start_time = current_time;
+
+        emit!(PresaleStarted {
+            presale: self.presale.key(),
+            start_time: current_time,
+        });
         
         Ok(())
     }
@@ -0,0 +1,220 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+
+use crate::{
+    errors::PresaleError,
+    events::PresaleUpdated,
+    state::{Level, LevelConfig, Presale, PresaleStatus, PurchaseLimits, QuoteMint, SaleMode, VestingSchedule},
+};
+
+// Configuration changes, `None` keeps the current value
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PresaleUpdate {
+    pub softcap_amount: Option<u64>,
+    pub hardcap_amount: Option<u64>,
+    pub start_time: Option<u64>,
+    pub end_time: Option<u64>,
+    pub levels: Option<Vec<LevelConfig>>,
+    // Levels added after the configured ones, a long level list is sent over several updates
+    pub append_levels: Option<Vec<LevelConfig>>,
+    pub vesting: Option<VestingSchedule>,
+    pub limits: Option<PurchaseLimits>,
+}
+
+impl PresaleUpdate {
+    // Whether the update touches nothing but the levels
+    pub fn is_levels_only(&self) -> bool {
+        self.softcap_amount.is_none()
+            && self.hardcap_amount.is_none()
+            && self.start_time.is_none()
+            && self.end_time.is_none()
+            && self.vesting.is_none()
+            && self.limits.is_none()
+    }
+
+    // Levels the presale has once the update is applied to `level_count` configured ones
+    fn level_count(&self, level_count: usize) -> usize {
+        self.levels.as_ref().map_or(level_count, |levels| levels.len())
+            + self.append_levels.as_ref().map_or(0, |levels| levels.len())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(update: PresaleUpdate)]
+pub struct UpdatePresale<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    // Replaces the presale token, only before any tokens are deposited
+    pub new_token_mint: Option<InterfaceAccount<'info, Mint>>,
+    // Replaces the primary quote mint, only while it is the only one accepted
+    pub new_usd_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+        constraint = presale.authority == admin.key() || presale.roles.operator == admin.key() @ PresaleError::Unauthorized,
+        realloc = Presale::space(update.level_count(presale.levels.len())),
+        realloc::payer = admin,
+        realloc::zero = false,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    // Vault of the current presale token, required with `new_token_mint` to show it is empty
+    #[account(
+        associated_token::mint = presale.token_mint_address,
+        associated_token::authority = presale,
+        associated_token::token_program = token_program
+    )]
+    pub vault_dog: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> UpdatePresale<'info> {
+    pub fn update_presale(&mut self, update: PresaleUpdate) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp as u64;
+
+        // The operator edits levels, the rest of the configuration is the authority's
+        if self.admin.key() != self.presale.authority {
+            require!(
+                update.is_levels_only() && self.new_token_mint.is_none() && self.new_usd_mint.is_none(),
+                PresaleError::Unauthorized
+            );
+        }
+
+        match self.presale.sync_status(current_time) {
+            PresaleStatus::Draft | PresaleStatus::Scheduled => self.update_before_launch(update)?,
+            PresaleStatus::Live | PresaleStatus::Paused => self.update_while_live(update)?,
+            _ => return Err(PresaleError::PresaleEnded.into()),
+        }
+
+        let presale = &self.presale;
+        presale.validate(current_time)?;
+
+        emit!(PresaleUpdated {
+            presale: presale.key(),
+            status: presale.status,
+        });
+
+        Ok(())
+    }
+
+    // Nothing has been sold yet, so every field may change
+    fn update_before_launch(&mut self, update: PresaleUpdate) -> Result<()> {
+        let presale = &mut self.presale;
+
+        if let Some(mint) = &self.new_token_mint {
+            // `deposit_token_amount` is the declared amount, the vault shows what was deposited
+            let vault_amount = self.vault_dog.as_ref().map(|vault| vault.amount);
+            require!(vault_amount == Some(0), PresaleError::MintChangeNotAllowed);
+            presale.token_mint_address = mint.key();
+            presale.token_decimals = mint.decimals;
+        }
+        if let Some(mint) = &self.new_usd_mint {
+            // Keeps the buyer PDAs' per-currency slots pointing at the same mints
+            require!(presale.quote_mints.len() == 1, PresaleError::MintChangeNotAllowed);
+            presale.usd_mint = mint.key();
+            presale.quote_mints[0] = QuoteMint::new(mint.key(), mint.decimals);
+        }
+
+        if let Some(softcap_amount) = update.softcap_amount {
+            presale.softcap_amount = softcap_amount;
+        }
+        if let Some(hardcap_amount) = update.hardcap_amount {
+            presale.hardcap_amount = hardcap_amount;
+        }
+        if let Some(start_time) = update.start_time {
+            presale.start_time = start_time;
+        }
+        if let Some(end_time) = update.end_time {
+            presale.end_time = end_time;
+        }
+        if let Some(levels) = update.levels {
+            presale.levels = levels.into_iter().map(Level::from).collect();
+            presale.current_level = 0;
+        }
+        if let Some(levels) = update.append_levels {
+            presale.levels.extend(levels.into_iter().map(Level::from));
+        }
+        if let Some(vesting) = update.vesting {
+            presale.vesting = vesting;
+        }
+        if let Some(limits) = update.limits {
+            presale.limits = limits;
+        }
+
+        Ok(())
+    }
+
+    // Buyers already hold a price and a schedule, only changes in their favour are allowed:
+    // a later end, a higher hardcap and new levels after the configured ones
+    fn update_while_live(&mut self, update: PresaleUpdate) -> Result<()> {
+        let presale = &mut self.presale;
+
+        require!(
+            self.new_token_mint.is_none()
+                && self.new_usd_mint.is_none()
+                && update.softcap_amount.is_none()
+                && update.start_time.is_none()
+                && update.vesting.is_none()
+                && update.limits.is_none(),
+            PresaleError::NotEditableWhileLive
+        );
+
+        if let Some(end_time) = update.end_time {
+            // Moving the end of an auction would raise its current price
+            require!(
+                end_time >= presale.end_time && !matches!(presale.sale_mode, SaleMode::DutchAuction { .. }),
+                PresaleError::NotEditableWhileLive
+            );
+            presale.end_time = end_time;
+        }
+        if let Some(hardcap_amount) = update.hardcap_amount {
+            // Overflow commitments and auction bids were sized against the advertised hardcap,
+            // moving it would change the allocation of everyone already in
+            require!(
+                hardcap_amount >= presale.hardcap_amount
+                    && !matches!(presale.sale_mode, SaleMode::Overflow { .. } | SaleMode::BatchAuction { .. }),
+                PresaleError::NotEditableWhileLive
+            );
+            presale.hardcap_amount = hardcap_amount;
+        }
+        if let Some(levels) = update.levels {
+            // Batch auction ticks already hold the demand bid at them
+            require!(
+                levels.len() >= presale.levels.len() && !presale.is_batch_auction(),
+                PresaleError::NotEditableWhileLive
+            );
+
+            let mut current_level = presale.current_level;
+            for (index, config) in levels.into_iter().enumerate() {
+                match presale.levels.get_mut(index) {
+                    // Configured levels stay as they are
+                    Some(level) if level.token_amount > 0 => {
+                        require!(level.matches(&config), PresaleError::NotEditableWhileLive);
+                        continue;
+                    }
+                    Some(level) => *level = config.into(),
+                    None => presale.levels.push(config.into()),
+                }
+                if config.token_amount > 0 && index < current_level as usize {
+                    current_level = index as u8;
+                }
+            }
+            // Every level before `current_level` is sold out or empty, so buys can safely
+            // resume from the first added one
+            presale.current_level = current_level;
+        }
+        if let Some(levels) = update.append_levels {
+            require!(!presale.is_batch_auction(), PresaleError::NotEditableWhileLive);
+            // `current_level` never passes the end of the list, so buys reach the appended
+            // levels once the ones before them sell out
+            presale.levels.extend(levels.into_iter().map(Level::from));
+        }
+
+        Ok(())
+    }
+}
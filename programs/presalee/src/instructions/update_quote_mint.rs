@@ -0,0 +1,27 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::PresaleError, state::Presale};
+
+#[derive(Accounts)]
+pub struct UpdateQuoteMint<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = presale.authority == admin.key() @ PresaleError::Unauthorized,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+}
+
+impl<'info> UpdateQuoteMint<'info> {
+    pub fn update_quote_mint(&mut self, mint: Pubkey, enabled: bool) -> Result<()> {
+        // Entries are never removed so refunds and withdrawals stay possible for every currency paid in
+        let index = self.presale.quote_index(&mint)?;
+        self.presale.quote_mints[index].enabled = enabled;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{transfer, Transfer};
+
+use crate::{errors::PresaleError, events::SolWithdrawn, state::{Presale, PAUSE_WITHDRAWALS}};
+
+#[derive(Accounts)]
+pub struct WithdrawSol<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = presale.roles.treasurer == admin.key() @ PresaleError::Unauthorized,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        bump = presale.bump,
+    )]
+    pub presale: Account<'info, Presale>,
+
+    #[account(
+        mut,
+        seeds = [b"sol_vault", presale.key().as_ref()],
+        bump
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> WithdrawSol<'info> {
+    pub fn withdraw_sol(&mut self, bumps: &WithdrawSolBumps) -> Result<()> {
+        let presale = &mut self.presale;
+        let clock = Clock::get()?;
+        let current_time = clock.unix_timestamp as u64;
+        presale.require_succeeded(current_time)?;
+        presale.require_not_paused(PAUSE_WITHDRAWALS)?;
+
+        let amount = self.sol_vault.lamports();
+
+        let presale_key = self.presale.key();
+        let seeds = &[
+            &b"sol_vault"[..],
+            presale_key.as_ref(),
+            &[bumps.sol_vault],
+        ];
+        let signers_seeds = &[&seeds[..]];
+
+        transfer(
+            CpiContext::new_with_signer(
+                self.system_program.to_account_info(),
+                Transfer {
+                    from: self.sol_vault.to_account_info(),
+                    to: self.admin.to_account_info(),
+                },
+                signers_seeds,
+            ),
+            amount,
+        )?;
+
+        emit!(SolWithdrawn {
+            presale: self.presale.key(),
+            admin: self.admin.key(),
+            lamports: amount,
+        });
+        Ok(())
+    }
+}
@@ -1,12 +1,13 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked},
+    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
 };
 
 use crate::{
     errors::PresaleError,
-    state::{Presale},
+    events::TokensWithdrawn,
+    state::{Presale, PresaleAction, PresaleStatus, PAUSE_WITHDRAWALS},
 };
 
 #[derive(Accounts)]
@@ -14,35 +15,38 @@ pub struct WithdrawToken<'info> {
     #[account(mut)]
     pub admin: Signer<'info>,
 
-    pub usd_mint: Account<'info, Mint>,
-    pub token_mint_address: Account<'info, Mint>,
+    pub usd_mint: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_program)]
+    pub token_mint_address: InterfaceAccount<'info, Mint>,
 
     #[account(
         mut,
         associated_token::mint = token_mint_address,
-        associated_token::authority = admin
+        associated_token::authority = admin,
+        associated_token::token_program = token_program
     )]
-    pub admin_ata: Account<'info, TokenAccount>,
+    pub admin_ata: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
         associated_token::mint = token_mint_address,
-        associated_token::authority = presale
+        associated_token::authority = presale,
+        associated_token::token_program = token_program
     )]
-    pub vault_dog: Account<'info, TokenAccount>,
+    pub vault_dog: InterfaceAccount<'info, TokenAccount>,
 
     
     #[account(
         mut,
         has_one = token_mint_address,
         has_one = usd_mint,
-        has_one = admin,
-        seeds = [b"dogx_presale", admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        constraint = presale.roles.treasurer == admin.key() @ PresaleError::Unauthorized,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
         bump = presale.bump,
     )]
     pub presale: Account<'info, Presale>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
@@ -52,21 +56,30 @@ impl<'info> WithdrawToken<'info>{
     let presale = &mut self.presale;
     let clock = Clock::get()?;
     let current_time = clock.unix_timestamp as u64;
-    let is_ended = !presale.is_live || current_time >= presale.end_time;
-    require!(is_ended, PresaleError::PresaleNotEnded);
+    presale.require_not_paused(PAUSE_WITHDRAWALS)?;
 
-    require!(
-            presale.sold_token_amount >= presale.softcap_amount,
-            PresaleError::SoftCapNotReached
-        );
-
-        // let amount = presale.sold_token_amount;
-        let amount = self.vault_dog.amount;
+        let amount = match presale.sync_status(current_time) {
+            // Sold tokens and referral bonuses stay in the vault to be claimed, only the unsold
+            // part is returned
+            PresaleStatus::Succeeded => {
+                // Batch auctions only know what they sold once the clearing price is set
+                presale.require_settled()?;
+                let owed_amount = presale.sold_token_amount.saturating_add(presale.referral_token_amount);
+                let unsold_amount = presale.deposit_token_amount.saturating_sub(owed_amount);
+                presale.deposit_token_amount = owed_amount;
+                presale.transition(PresaleAction::Finalize, current_time)?;
+                unsold_amount
+            }
+            // Nobody receives tokens from a failed or cancelled sale
+            PresaleStatus::Failed | PresaleStatus::Cancelled => self.vault_dog.amount,
+            PresaleStatus::Finalized => return Err(PresaleError::AlreadyClaimed.into()),
+            _ => return Err(PresaleError::PresaleNotEnded.into()),
+        };
 
         let binding = self.presale.admin.key();
          let seeds = &[
             &b"dogx_presale"[..],
-            &binding.as_ref(),
+            binding.as_ref(),
             &self.presale.seed.to_le_bytes(),
             &[self.presale.bump],
         ];
@@ -87,6 +100,12 @@ impl<'info> WithdrawToken<'info>{
         amount,
         self.token_mint_address.decimals
     )?;
+
+        emit!(TokensWithdrawn {
+            presale: self.presale.key(),
+            admin: self.admin.key(),
+            amount,
+        });
         Ok(())
     }
 }
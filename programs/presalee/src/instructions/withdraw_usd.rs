@@ -1,12 +1,13 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked},
+    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
 };
 
 use crate::{
     errors::PresaleError,
-    state::{Presale},
+    events::QuoteWithdrawn,
+    state::{Presale, PAUSE_WITHDRAWALS},
 };
 
 #[derive(Accounts)]
@@ -14,35 +15,38 @@ pub struct WithdrawUsd<'info> {
     #[account(mut)]
     pub admin: Signer<'info>,
 
-    pub usd_mint: Account<'info, Mint>,
-    pub token_mint_address: Account<'info, Mint>,
+    // Quote mint being withdrawn, one currency per call
+    #[account(mint::token_program = token_program)]
+    pub usd_mint: InterfaceAccount<'info, Mint>,
+    pub token_mint_address: InterfaceAccount<'info, Mint>,
 
     #[account(
         mut,
         associated_token::mint = usd_mint,
-        associated_token::authority = admin
+        associated_token::authority = admin,
+        associated_token::token_program = token_program
     )]
-    pub admin_ata: Account<'info, TokenAccount>,
+    pub admin_ata: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
         associated_token::mint = usd_mint,
-        associated_token::authority = presale
+        associated_token::authority = presale,
+        associated_token::token_program = token_program
     )]
-    pub vault_usd: Account<'info, TokenAccount>,
+    pub vault_usd: InterfaceAccount<'info, TokenAccount>,
 
     
     #[account(
         mut,
         has_one = token_mint_address,
-        has_one = usd_mint,
-        has_one = admin,
-        seeds = [b"dogx_presale", admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
+        constraint = presale.roles.treasurer == admin.key() @ PresaleError::Unauthorized,
+        seeds = [b"dogx_presale", presale.admin.key().as_ref(), presale.seed.to_le_bytes().as_ref()],
         bump = presale.bump,
     )]
     pub presale: Account<'info, Presale>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
@@ -52,20 +56,18 @@ impl<'info> WithdrawUsd<'info>{
     let presale = &mut self.presale;
     let clock = Clock::get()?;
     let current_time = clock.unix_timestamp as u64;
-    let is_ended = !presale.is_live || current_time >= presale.end_time;
-    require!(is_ended, PresaleError::PresaleNotEnded);
+    presale.require_succeeded(current_time)?;
+    presale.require_not_paused(PAUSE_WITHDRAWALS)?;
 
-    require!(
-            presale.sold_token_amount >= presale.softcap_amount,
-            PresaleError::SoftCapNotReached
-        );
-
-        let amount = self.vault_usd.amount;
+        // Only accepted quote currencies can be withdrawn, minus what is still owed to buyers
+        let quote_index = presale.quote_index(&self.usd_mint.key())?;
+        let amount = self.vault_usd.amount.saturating_sub(presale.reserved_quote(quote_index)?);
+        require!(amount > 0, PresaleError::NothingToClaim);
 
         let binding = self.presale.admin.key();
          let seeds = &[
             &b"dogx_presale"[..],
-            &binding.as_ref(),
+            binding.as_ref(),
             &self.presale.seed.to_le_bytes(),
             &[self.presale.bump],
         ];
@@ -86,6 +88,13 @@ impl<'info> WithdrawUsd<'info>{
         amount,
         self.usd_mint.decimals
     )?;
+
+        emit!(QuoteWithdrawn {
+            presale: self.presale.key(),
+            admin: self.admin.key(),
+            quote_mint: self.usd_mint.key(),
+            amount,
+        });
         Ok(())
     }
 }
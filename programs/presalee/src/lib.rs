@@ -1,9 +1,11 @@
-#![allow(unexpected_cfgs, deprecated)]
+#![allow(unexpected_cfgs, deprecated, clippy::too_many_arguments)]
 use anchor_lang::prelude::*;
 
 pub mod instructions;
 pub mod state;
 pub mod errors;
+pub mod events;
+pub mod utils;
 
 use instructions::*;
 use state::*;
@@ -22,23 +24,20 @@ pub mod presalee {
         softcap_amount: u64,
         hardcap_amount: u64,
         deposit_token_amount: u64,
-        levels: [Level; 7], 
+        levels: Vec<LevelConfig>,
         sold_token_amount: u64,
         start_time: u64,
-        end_time: u64 ) -> Result<()> {
-            ctx.accounts.init_presale(seed, token_mint_address, usd_mint, softcap_amount, hardcap_amount, deposit_token_amount, levels, sold_token_amount, start_time, end_time, &ctx.bumps)?;
+        end_time: u64,
+        auto_start: bool,
+        vesting: VestingSchedule,
+        limits: PurchaseLimits,
+        sale_mode: SaleMode ) -> Result<()> {
+            ctx.accounts.init_presale(seed, token_mint_address, usd_mint, softcap_amount, hardcap_amount, deposit_token_amount, levels, sold_token_amount, start_time, end_time, auto_start, vesting, limits, sale_mode, &ctx.bumps)?;
         Ok(())
     }
 
-    pub fn init_user(
-        ctx: Context<InitUser>,
-        buy_quote_amount: u64,
-        buy_token_amount: u64, 
-        buy_time: u64, 
-        claim_amount: u64, 
-        claim_time: u64,
-    ) -> Result<()> {
-        ctx.accounts.init_user(buy_quote_amount, buy_token_amount, buy_time, claim_amount, claim_time, &ctx.bumps)?;
+    pub fn init_user(ctx: Context<InitUser>) -> Result<()> {
+        ctx.accounts.init_user(&ctx.bumps)?;
         Ok(())
     }
 
@@ -52,8 +51,30 @@ pub mod presalee {
         Ok(())
     }
 
-     pub fn buy_tokens(ctx: Context<BuyToken>, payment: u64) -> Result<()> {
-        ctx.accounts.buy_tokens(payment)?;
+     pub fn buy_tokens(
+        ctx: Context<BuyToken>,
+        payment: u64,
+        min_tokens_out: u64,
+        max_level: Option<u8>,
+        deadline: u64,
+        fill_mode: FillMode,
+        max_allocation: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        ctx.accounts.buy_tokens(payment, min_tokens_out, max_level, deadline, fill_mode, max_allocation, proof, &ctx.bumps)?;
+        Ok(())
+    }
+
+    pub fn buy_exact_tokens(
+        ctx: Context<BuyToken>,
+        token_amount: u64,
+        max_payment: u64,
+        max_level: Option<u8>,
+        deadline: u64,
+        max_allocation: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        ctx.accounts.buy_exact_tokens(token_amount, max_payment, max_level, deadline, max_allocation, proof, &ctx.bumps)?;
         Ok(())
     }
 
@@ -81,9 +102,150 @@ pub mod presalee {
         Ok(())
     }
 
+    pub fn pause_presale(ctx: Context<PausePresale>, mask: u8, extend_end_time: bool) -> Result<()> {
+        ctx.accounts.pause_presale(mask, extend_end_time)?;
+        Ok(())
+    }
+
+    pub fn resume_presale(ctx: Context<ResumePresale>, mask: u8) -> Result<()> {
+        ctx.accounts.resume_presale(mask)?;
+        Ok(())
+    }
+
+    pub fn cancel_presale(ctx: Context<CancelPresale>) -> Result<()> {
+        ctx.accounts.cancel_presale()?;
+        Ok(())
+    }
+
     pub fn close_presale(ctx: Context<ClosePresale>) -> Result<()> {
         ctx.accounts.close_presale()?;
         Ok(())
     }
-}
 
+    pub fn set_whitelist(ctx: Context<SetWhitelist>, whitelist_root: Option<[u8; 32]>) -> Result<()> {
+        ctx.accounts.set_whitelist(whitelist_root)?;
+        Ok(())
+    }
+
+    pub fn add_quote_mint(ctx: Context<AddQuoteMint>) -> Result<()> {
+        ctx.accounts.add_quote_mint()?;
+        Ok(())
+    }
+
+    pub fn update_quote_mint(ctx: Context<UpdateQuoteMint>, mint: Pubkey, enabled: bool) -> Result<()> {
+        ctx.accounts.update_quote_mint(mint, enabled)?;
+        Ok(())
+    }
+
+    pub fn set_sol_price_feed(ctx: Context<SetSolPriceFeed>, sol_payment: Option<SolPriceFeed>) -> Result<()> {
+        ctx.accounts.set_sol_price_feed(sol_payment)?;
+        Ok(())
+    }
+
+    pub fn buy_tokens_with_sol(
+        ctx: Context<BuyTokenSol>,
+        lamports: u64,
+        min_tokens_out: u64,
+        max_level: Option<u8>,
+        deadline: u64,
+        fill_mode: FillMode,
+        max_allocation: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        ctx.accounts.buy_tokens_with_sol(lamports, min_tokens_out, max_level, deadline, fill_mode, max_allocation, proof, &ctx.bumps)?;
+        Ok(())
+    }
+
+    pub fn claim_sol_refund(ctx: Context<ClaimSolRefund>) -> Result<()> {
+        ctx.accounts.claim_sol_refund(&ctx.bumps)?;
+        Ok(())
+    }
+
+    pub fn withdraw_sol(ctx: Context<WithdrawSol>) -> Result<()> {
+        ctx.accounts.withdraw_sol(&ctx.bumps)?;
+        Ok(())
+    }
+
+    pub fn propose_admin(ctx: Context<ProposeAdmin>, new_admin: Pubkey) -> Result<()> {
+        ctx.accounts.propose_admin(new_admin)?;
+        Ok(())
+    }
+
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        ctx.accounts.accept_admin()?;
+        Ok(())
+    }
+
+    pub fn set_role(ctx: Context<SetRole>, role: PresaleRole, key: Pubkey) -> Result<()> {
+        ctx.accounts.set_role(role, key)?;
+        Ok(())
+    }
+
+    pub fn update_presale(ctx: Context<UpdatePresale>, update: PresaleUpdate) -> Result<()> {
+        ctx.accounts.update_presale(update)?;
+        Ok(())
+    }
+
+    pub fn commit_quote(
+        ctx: Context<BuyToken>,
+        amount: u64,
+        deadline: u64,
+        max_allocation: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        ctx.accounts.commit_quote(amount, deadline, max_allocation, proof, &ctx.bumps)?;
+        Ok(())
+    }
+
+    pub fn settle_commitment(ctx: Context<SettleCommitment>) -> Result<()> {
+        ctx.accounts.settle_commitment()?;
+        Ok(())
+    }
+
+    pub fn claim_rebate(ctx: Context<ClaimRebate>) -> Result<()> {
+        ctx.accounts.claim_rebate()?;
+        Ok(())
+    }
+
+    pub fn place_bid(
+        ctx: Context<PlaceBid>,
+        level: u8,
+        quantity: u64,
+        deadline: u64,
+        max_allocation: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        ctx.accounts.place_bid(level, quantity, deadline, max_allocation, proof, &ctx.bumps)?;
+        Ok(())
+    }
+
+    pub fn cancel_bid(ctx: Context<CancelBid>) -> Result<()> {
+        ctx.accounts.cancel_bid()?;
+        Ok(())
+    }
+
+    pub fn settle_auction(ctx: Context<SettleAuction>) -> Result<()> {
+        ctx.accounts.settle_auction()?;
+        Ok(())
+    }
+
+    pub fn settle_bid(ctx: Context<SettleBid>) -> Result<()> {
+        ctx.accounts.settle_bid(&ctx.bumps)?;
+        Ok(())
+    }
+
+    pub fn set_referral_config(ctx: Context<SetReferralConfig>, referral: Option<ReferralConfig>) -> Result<()> {
+        ctx.accounts.set_referral_config(referral)?;
+        Ok(())
+    }
+
+    pub fn register_referrer(ctx: Context<RegisterReferrer>) -> Result<()> {
+        ctx.accounts.register_referrer(&ctx.bumps)?;
+        Ok(())
+    }
+
+    pub fn claim_referral(ctx: Context<ClaimReferral>) -> Result<()> {
+        ctx.accounts.claim_referral()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::PresaleError, state::{Presale, PresaleStatus, SaleMode}};
+
+// A buyer's standing order in a batch auction, one per buyer and presale
+#[account]
+#[derive(InitSpace)]
+pub struct Bid {
+    // Buyer who placed the bid
+    pub bidder: Pubkey,
+    // Price tick bid at, an index into `Presale::levels`
+    pub level: u8,
+    // Tokens wanted at that price or lower
+    pub quantity: u64,
+    // Quote locked for the bid, `quantity` at the tick price
+    pub deposit: u64,
+    // Time the bid was placed
+    pub bid_time: u64,
+    // bump
+    pub bump: u8
+}
+
+impl Presale {
+    pub fn is_batch_auction(&self) -> bool {
+        matches!(self.sale_mode, SaleMode::BatchAuction { .. })
+    }
+
+    // Tokens bid across every price tick. In batch auctions each level is a price tick and its
+    // `tokens_sold` holds the demand bid at that price.
+    pub fn total_demand(&self) -> u64 {
+        self.levels
+            .iter()
+            .fold(0_u64, |total, level| total.saturating_add(level.tokens_sold))
+    }
+
+    // Clearing tick of a finished batch auction and the tokens left for bids at that tick: the
+    // highest price at which demand from that price up covers the hardcap, or the lowest tick
+    // bid at when it never does. An auction without bids clears at its lowest tick.
+    pub fn clearing_level(&self) -> Result<(usize, u64)> {
+        require!(self.is_batch_auction(), PresaleError::WrongSaleMode);
+
+        let mut remaining = self.hardcap_amount;
+        let mut clearing = None;
+        for (index, level) in self.levels.iter().enumerate().rev() {
+            if level.tokens_sold == 0 {
+                continue;
+            }
+            clearing = Some((index, remaining.min(level.tokens_sold)));
+            if level.tokens_sold >= remaining {
+                break;
+            }
+            remaining -= level.tokens_sold;
+        }
+
+        Ok(clearing.unwrap_or((0, 0)))
+    }
+
+    // Tokens a bid of `quantity` at tick `level` wins, bids at the clearing tick share what is
+    // left pro rata, rounded down
+    pub fn bid_allocation(&self, level: usize, quantity: u64) -> Result<u64> {
+        let (clearing_level, clearing_supply) = self.clearing_level()?;
+        if level > clearing_level {
+            return Ok(quantity);
+        }
+        if level < clearing_level {
+            return Ok(0);
+        }
+
+        (quantity as u128)
+            .checked_mul(clearing_supply as u128)
+            .map(|x| x / self.levels[clearing_level].tokens_sold as u128)
+            .and_then(|x| u64::try_from(x).ok())
+            .ok_or(PresaleError::CalculationOverflow.into())
+    }
+
+    // Fixes the clearing price of a successful auction and the tokens it sold, once. Returns the
+    // total demand bid
+    pub fn settle_clearing_price(&mut self) -> Result<u64> {
+        require!(self.clearing_price == 0, PresaleError::AuctionAlreadySettled);
+
+        let (clearing_level, _) = self.clearing_level()?;
+        let total_demand = self.total_demand();
+        self.clearing_price = self.levels[clearing_level].price;
+        self.sold_token_amount = total_demand.min(self.hardcap_amount);
+        Ok(total_demand)
+    }
+
+    // Tokens `bid` wins, the quote it spends on them at the clearing price and the rest of its
+    // deposit to refund. Bids in a failed or cancelled auction are refunded whole.
+    pub fn bid_settlement(&mut self, bid: &Bid, quote_decimals: u8, now: u64) -> Result<(u64, u64, u64)> {
+        let (tokens, amount_spent) = match self.sync_status(now) {
+            PresaleStatus::Succeeded | PresaleStatus::Finalized => {
+                self.require_settled()?;
+                let tokens = self.bid_allocation(bid.level as usize, bid.quantity)?;
+                let cost = self.quote_for_tokens(tokens, self.clearing_price, quote_decimals)?;
+                (tokens, cost.min(bid.deposit))
+            }
+            PresaleStatus::Failed | PresaleStatus::Cancelled => (0, 0),
+            _ => return Err(PresaleError::PresaleNotEnded.into()),
+        };
+        Ok((tokens, amount_spent, bid.deposit - amount_spent))
+    }
+
+    // Claims need the clearing price of a successful batch auction to be settled first
+    pub fn require_settled(&self) -> Result<()> {
+        require!(!self.is_batch_auction() || self.clearing_price > 0, PresaleError::AuctionNotSettled);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::testing::{level, presale, DECIMALS, END_TIME, UNIT};
+
+    // 1,000 tokens bid for at 0.01, 0.02 and 0.03
+    fn batch_auction(softcap_amount: u64) -> Presale {
+        let ticks = vec![level(0, 10_000, 0), level(0, 20_000, 0), level(0, 30_000, 0)];
+        presale(SaleMode::BatchAuction { token_amount: 1_000 * UNIT }, ticks, softcap_amount, 1_000 * UNIT)
+    }
+
+    // Records a bid like `place_bid`, depositing `quantity` at the tick price
+    fn place(presale: &mut Presale, level: u8, quantity: u64) -> Bid {
+        presale.levels[level as usize].tokens_sold += quantity;
+        let price = presale.levels[level as usize].price;
+        let deposit = presale.quote_for_tokens(quantity, price, DECIMALS).unwrap();
+        Bid { bidder: Pubkey::default(), level, quantity, deposit, bid_time: 0, bump: 0 }
+    }
+
+    fn settle(presale: &mut Presale, bid: &Bid) -> (u64, u64, u64) {
+        presale.bid_settlement(bid, DECIMALS, END_TIME).unwrap()
+    }
+
+    #[test]
+    fn demand_below_the_hardcap_clears_at_the_lowest_bid() {
+        let mut presale = batch_auction(0);
+        let high = place(&mut presale, 2, 300 * UNIT);
+        let low = place(&mut presale, 0, 200 * UNIT);
+
+        assert_eq!(presale.clearing_level().unwrap(), (0, 200 * UNIT));
+        assert_eq!(presale.sync_status(END_TIME), PresaleStatus::Succeeded);
+        assert_eq!(presale.settle_clearing_price().unwrap(), 500 * UNIT);
+        assert_eq!(presale.clearing_price, 10_000);
+        assert_eq!(presale.sold_token_amount, 500 * UNIT);
+
+        // Every bid fills, the 0.03 bid is refunded the 0.02 it bid above the clearing price
+        assert_eq!(settle(&mut presale, &high), (300 * UNIT, 3 * UNIT, 6 * UNIT));
+        assert_eq!(settle(&mut presale, &low), (200 * UNIT, 2 * UNIT, 0));
+    }
+
+    #[test]
+    fn demand_above_the_hardcap_fills_the_marginal_tick_pro_rata() {
+        let mut presale = batch_auction(0);
+        let top = place(&mut presale, 2, 600 * UNIT);
+        let marginal_large = place(&mut presale, 1, 600 * UNIT);
+        let marginal_small = place(&mut presale, 1, 200 * UNIT);
+        let losing = place(&mut presale, 0, 500 * UNIT);
+
+        // 600 above the 0.02 tick leaves 400 for the 800 bid at it
+        assert_eq!(presale.clearing_level().unwrap(), (1, 400 * UNIT));
+        assert_eq!(presale.sync_status(END_TIME), PresaleStatus::Succeeded);
+        assert_eq!(presale.settle_clearing_price().unwrap(), 1_900 * UNIT);
+        assert_eq!(presale.clearing_price, 20_000);
+        assert_eq!(presale.sold_token_amount, 1_000 * UNIT);
+
+        // Bids above the clearing tick fill whole and get back max price - clearing price
+        assert_eq!(settle(&mut presale, &top), (600 * UNIT, 12 * UNIT, 6 * UNIT));
+        // Bids at it get half of what they asked for
+        assert_eq!(settle(&mut presale, &marginal_large), (300 * UNIT, 6 * UNIT, 6 * UNIT));
+        assert_eq!(settle(&mut presale, &marginal_small), (100 * UNIT, 2 * UNIT, 2 * UNIT));
+        // Bids below it win nothing and are refunded whole
+        assert_eq!(settle(&mut presale, &losing), (0, 0, 5 * UNIT));
+    }
+
+    #[test]
+    fn pro_rata_fills_never_exceed_the_hardcap() {
+        let mut presale = batch_auction(0);
+        place(&mut presale, 2, 1);
+        let bids: Vec<Bid> = [333, 334, 335].iter().map(|quantity| place(&mut presale, 1, *quantity * UNIT)).collect();
+        presale.sync_status(END_TIME);
+        presale.settle_clearing_price().unwrap();
+
+        // The single unit bid higher leaves one unit short of the hardcap for the marginal tick,
+        // rounding down loses less than a unit per bid
+        let supply = 1_000 * UNIT - 1;
+        assert_eq!(presale.clearing_level().unwrap(), (1, supply));
+        let filled: u64 = bids.iter().map(|bid| settle(&mut presale, bid).0).sum();
+        assert!(filled <= supply && supply - filled < bids.len() as u64);
+    }
+
+    #[test]
+    fn failed_auction_refunds_every_bid_whole() {
+        let mut presale = batch_auction(600 * UNIT);
+        let bid = place(&mut presale, 2, 500 * UNIT);
+
+        assert_eq!(presale.sync_status(END_TIME), PresaleStatus::Failed);
+        assert_eq!(settle(&mut presale, &bid), (0, 0, 15 * UNIT));
+    }
+
+    #[test]
+    fn settlement_runs_once_and_only_after_the_auction() {
+        let mut presale = batch_auction(0);
+        let bid = place(&mut presale, 1, 100 * UNIT);
+
+        assert_eq!(
+            presale.bid_settlement(&bid, DECIMALS, END_TIME - 1).unwrap_err(),
+            PresaleError::PresaleNotEnded.into()
+        );
+        assert_eq!(
+            presale.bid_settlement(&bid, DECIMALS, END_TIME).unwrap_err(),
+            PresaleError::AuctionNotSettled.into()
+        );
+
+        presale.settle_clearing_price().unwrap();
+        assert_eq!(presale.settle_clearing_price().unwrap_err(), PresaleError::AuctionAlreadySettled.into());
+    }
+
+    #[test]
+    fn bids_cannot_be_cancelled_after_the_end() {
+        let mut presale = batch_auction(0);
+        place(&mut presale, 1, 100 * UNIT);
+
+        assert!(presale.require_live(END_TIME - 1).is_ok());
+        assert_eq!(presale.require_live(END_TIME).unwrap_err(), PresaleError::PresaleEnded.into());
+    }
+}
@@ -0,0 +1,202 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::PresaleError,
+    state::{pow10, Presale, SaleMode, PRICE_DECIMALS},
+    utils::{exp2_wad, exp2_wad_up, mul_div, LN_2_WAD, WAD},
+};
+
+// Upper bound on the doublings of an exponential curve across the whole sale
+pub const MAX_CURVE_DOUBLINGS: u64 = 64;
+// Each bisection step at least halves the candidate range, 64 steps cover any u64 amount
+const MAX_BISECTION_STEPS: u32 = 64;
+
+// Price as a function of the tokens sold, starting at the curve's `base_price`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(InitSpace)]
+pub enum CurveShape {
+    Linear { end_price: u64 },              // Rises evenly to `end_price` once every token is sold
+    Exponential { doubling_amount: u64 },   // Doubles every `doubling_amount` tokens sold
+}
+
+impl Presale {
+    // Spot price once `sold` tokens are sold, rounded down
+    pub fn curve_price(&self, sold: u64) -> Result<u64> {
+        let SaleMode::BondingCurve { token_amount, base_price, shape } = self.sale_mode else {
+            return Err(PresaleError::WrongSaleMode.into());
+        };
+
+        let price = match shape {
+            CurveShape::Linear { end_price } => {
+                mul_div((end_price - base_price) as u128, sold as u128, token_amount as u128, false)
+                    .map(|rise| base_price as u128 + rise)
+            }
+            CurveShape::Exponential { doubling_amount } => {
+                mul_div(sold as u128, WAD, doubling_amount as u128, false)
+                    .and_then(exp2_wad)
+                    .and_then(|growth| mul_div(base_price as u128, growth, WAD, false))
+            }
+        };
+        price
+            .and_then(|price| u64::try_from(price).ok())
+            .ok_or(PresaleError::CalculationOverflow.into())
+    }
+
+    // Quote base units of a `quote_decimals` mint owed for the tokens between `sold` and
+    // `sold + tokens`: the integral of the price over that range, rounded up
+    pub fn curve_cost(&self, sold: u64, tokens: u64, quote_decimals: u8) -> Result<u64> {
+        let SaleMode::BondingCurve { token_amount, base_price, shape } = self.sale_mode else {
+            return Err(PresaleError::WrongSaleMode.into());
+        };
+        let from = sold as u128;
+        let to = from + tokens as u128;
+
+        // Value in `PRICE_DECIMALS` units times token base units
+        let value = match shape {
+            // base * tokens + (end - base) * (to^2 - from^2) / (2 * token_amount)
+            CurveShape::Linear { end_price } => {
+                (tokens as u128)
+                    .checked_mul(from + to)
+                    .and_then(|area| mul_div((end_price - base_price) as u128, area, 2 * token_amount as u128, true))
+                    .and_then(|rise| rise.checked_add(base_price as u128 * tokens as u128))
+            }
+            // base * doubling_amount * (2^(to / doubling_amount) - 2^(from / doubling_amount)) / ln 2,
+            // the upper endpoint rounded up and the lower one down so the cost is never short
+            CurveShape::Exponential { doubling_amount } => {
+                let doubling_amount = doubling_amount as u128;
+                let start = mul_div(from, WAD, doubling_amount, false).and_then(exp2_wad);
+                let end = mul_div(to, WAD, doubling_amount, true).and_then(exp2_wad_up);
+                start
+                    .zip(end)
+                    .and_then(|(start, end)| {
+                        mul_div(base_price as u128 * doubling_amount, end - start, LN_2_WAD, true)
+                    })
+            }
+        };
+
+        let quote_scale = pow10(quote_decimals as u32)?;
+        let value_scale = pow10(self.token_decimals as u32 + PRICE_DECIMALS)?;
+        value
+            .and_then(|value| mul_div(value, quote_scale, value_scale, true))
+            .and_then(|cost| u64::try_from(cost).ok())
+            .ok_or(PresaleError::CalculationOverflow.into())
+    }
+
+    // Most tokens `payment` buys on the curve, at most `available`. The curve has no closed-form
+    // inverse in integers, so this bisects between the spot-price bound and zero.
+    pub fn curve_tokens_for_quote(&self, payment: u64, available: u64, quote_decimals: u8) -> Result<u64> {
+        let sold = self.sold_token_amount;
+
+        // Every token costs at least the current spot price, a bound past u64 leaves `available`
+        let spot_price = self.curve_price(sold)?.max(1);
+        let mut low = 0_u64;
+        let mut high = self
+            .tokens_for_quote(payment, spot_price, quote_decimals)
+            .map_or(available, |tokens| tokens.min(available));
+
+        for _ in 0..MAX_BISECTION_STEPS {
+            if low >= high {
+                break;
+            }
+            let middle = low + (high - low).div_ceil(2);
+            if self.curve_cost(sold, middle, quote_decimals)? <= payment {
+                low = middle;
+            } else {
+                high = middle - 1;
+            }
+        }
+
+        Ok(low)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::testing::{presale, DECIMALS, UNIT};
+
+    // 1,000 tokens from 0.01 to 0.03
+    fn linear() -> Presale {
+        let shape = CurveShape::Linear { end_price: 30_000 };
+        let sale_mode = SaleMode::BondingCurve { token_amount: 1_000 * UNIT, base_price: 10_000, shape };
+        presale(sale_mode, Vec::new(), 0, 1_000 * UNIT)
+    }
+
+    // 400 tokens from 0.01, doubling every 100
+    fn exponential() -> Presale {
+        let shape = CurveShape::Exponential { doubling_amount: 100 * UNIT };
+        let sale_mode = SaleMode::BondingCurve { token_amount: 400 * UNIT, base_price: 10_000, shape };
+        presale(sale_mode, Vec::new(), 0, 400 * UNIT)
+    }
+
+    #[test]
+    fn linear_cost_matches_the_trapezoid() {
+        let presale = linear();
+
+        assert_eq!(presale.curve_price(500 * UNIT).unwrap(), 20_000);
+        // 500 tokens averaging 0.015, then 500 averaging 0.025
+        assert_eq!(presale.curve_cost(0, 500 * UNIT, DECIMALS).unwrap(), 7_500_000);
+        assert_eq!(presale.curve_cost(500 * UNIT, 500 * UNIT, DECIMALS).unwrap(), 12_500_000);
+        assert_eq!(presale.curve_cost(0, 1_000 * UNIT, DECIMALS).unwrap(), 20 * UNIT);
+    }
+
+    #[test]
+    fn exponential_cost_matches_the_integral() {
+        let presale = exponential();
+
+        assert_eq!(presale.curve_price(100 * UNIT).unwrap(), 20_000);
+        assert_eq!(presale.curve_price(200 * UNIT).unwrap(), 40_000);
+        // 1 * 100 / ln 2 = 1.44269504..., then 3 * 100 / ln 2 = 4.32808512...
+        assert_eq!(presale.curve_cost(0, 100 * UNIT, DECIMALS).unwrap(), 1_442_696);
+        assert_eq!(presale.curve_cost(0, 200 * UNIT, DECIMALS).unwrap(), 4_328_086);
+        assert_eq!(presale.curve_cost(100 * UNIT, 100 * UNIT, DECIMALS).unwrap(), 2_885_391);
+    }
+
+    #[test]
+    fn split_purchases_never_cost_less() {
+        for presale in [linear(), exponential()] {
+            for (first, second) in [(1, 1), (3 * UNIT, 7 * UNIT + 1), (99 * UNIT + 7, 101 * UNIT)] {
+                let whole = presale.curve_cost(0, first + second, DECIMALS).unwrap();
+                let split = presale.curve_cost(0, first, DECIMALS).unwrap()
+                    + presale.curve_cost(first, second, DECIMALS).unwrap();
+                assert!(split >= whole && split - whole <= 2);
+            }
+        }
+    }
+
+    #[test]
+    fn exact_in_buys_the_most_the_payment_covers() {
+        for mut presale in [linear(), exponential()] {
+            presale.sold_token_amount = 37 * UNIT + 11;
+            let sold = presale.sold_token_amount;
+            let available = presale.hardcap_amount - sold;
+
+            for payment in [1, 999, UNIT, 3 * UNIT + 17] {
+                let tokens = presale.curve_tokens_for_quote(payment, available, DECIMALS).unwrap();
+                assert!(presale.curve_cost(sold, tokens, DECIMALS).unwrap() <= payment);
+                assert!(presale.curve_cost(sold, tokens + 1, DECIMALS).unwrap() > payment);
+            }
+        }
+    }
+
+    #[test]
+    fn exact_out_cost_buys_back_at_least_the_tokens() {
+        for mut presale in [linear(), exponential()] {
+            presale.sold_token_amount = 150 * UNIT;
+            let sold = presale.sold_token_amount;
+            let available = presale.hardcap_amount - sold;
+
+            for tokens in [1, UNIT, 25 * UNIT + 3] {
+                let cost = presale.curve_cost(sold, tokens, DECIMALS).unwrap();
+                assert!(presale.curve_tokens_for_quote(cost, available, DECIMALS).unwrap() >= tokens);
+            }
+        }
+    }
+
+    #[test]
+    fn exact_in_is_capped_by_the_available_tokens() {
+        let presale = linear();
+
+        assert_eq!(presale.curve_tokens_for_quote(u64::MAX / 2, 10 * UNIT, DECIMALS).unwrap(), 10 * UNIT);
+    }
+}
@@ -1,5 +1,17 @@
+pub mod bid;
+pub mod bonding_curve;
 pub mod presale_info;
+pub mod presale_status;
+pub mod referral;
+pub mod sale_mode;
 pub mod user_info;
+#[cfg(test)]
+pub mod testing;
 
+pub use bid::*;
+pub use bonding_curve::*;
 pub use presale_info::*;
-pub use user_info::*;
\ No newline at end of file
+pub use presale_status::*;
+pub use referral::*;
+pub use sale_mode::*;
+pub use user_info::*;
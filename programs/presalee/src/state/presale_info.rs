@@ -1,23 +1,65 @@
 use anchor_lang::prelude::*;
 
+use crate::{errors::PresaleError, state::{CurveShape, PresaleStatus, ReferralConfig, SaleMode, UserInfo, MAX_CURVE_DOUBLINGS}, utils::{verify_proof, whitelist_leaf}};
+
+// Upper bound on the stablecoins a single presale accepts
+pub const MAX_QUOTE_MINTS: usize = 4;
+// Upper bound on the price levels of a presale, the account is sized for the levels it has.
+// More levels than fit in one transaction are appended in batches through `update_presale`
+pub const MAX_LEVELS: usize = 32;
+// Level prices are in 10^-6 units of the quote currency per whole token, whatever the decimals
+// of the presale token and of the quote mint paid with
+pub const PRICE_DECIMALS: u32 = 6;
+
+// 10^`exponent`, mint decimals are unbounded so scales past u128 fail instead of panicking
+pub(crate) fn pow10(exponent: u32) -> Result<u128> {
+    10_u128.checked_pow(exponent).ok_or(PresaleError::MathOverflow.into())
+}
+// Operations that `pause_presale` can halt, combined into `Presale::pause_flags`
+pub const PAUSE_BUYS: u8 = 1 << 0;
+pub const PAUSE_CLAIMS: u8 = 1 << 1;
+pub const PAUSE_REFUNDS: u8 = 1 << 2;
+pub const PAUSE_WITHDRAWALS: u8 = 1 << 3;
+pub const PAUSE_ALL: u8 = PAUSE_BUYS | PAUSE_CLAIMS | PAUSE_REFUNDS | PAUSE_WITHDRAWALS;
+
 #[account]
 #[derive(InitSpace)]
 pub struct Presale{
     pub seed: u64,
-    // Authority of the presale
+    // Creator of the presale, part of the PDA seeds and never changes
     pub admin: Pubkey,
-    // Presale is buyable
-    pub is_live: bool,
+    // Key currently allowed to manage the presale
+    pub authority: Pubkey,
+    // Proposed new authority, takes over once it accepts
+    pub pending_authority: Option<Pubkey>,
+    // Keys allowed to run day-to-day operations and move funds
+    pub roles: PresaleRoles,
+    // Pricing and allocation model, fixed at creation
+    pub sale_mode: SaleMode,
+    // Lifecycle status, see `Presale::transition`
+    pub status: PresaleStatus,
+    // Operations currently halted, `PAUSE_*` bits
+    pub pause_flags: u8,
+    // When buys were paused
+    pub paused_at: u64,
+    // Push `end_time` back by the paused duration on resume
+    pub extend_on_resume: bool,
     // Current level of the presale
     pub current_level: u8,     
-    // Array of presale levels     
-    pub levels: [Level; 7],         
+    // Presale levels, `Presale::space` sizes the account for however many there are
+    #[max_len(0)]
+    pub levels: Vec<Level>,
     // Total amount of presale tokens sold during the presale
     pub sold_token_amount: u64,
     // Mint address of the presale token (DGX)
     pub token_mint_address: Pubkey,
-    // Mint address of USDT
+    // Mint address of USDT, the primary quote currency levels are priced in
     pub usd_mint: Pubkey,
+    // Decimals of the presale token mint
+    pub token_decimals: u8,
+    // Accepted quote currencies, the first entry is always `usd_mint`
+    #[max_len(MAX_QUOTE_MINTS)]
+    pub quote_mints: Vec<QuoteMint>,
     // Softcap (Minimum funds required)
     pub softcap_amount: u64,
     // Hardcap (Maximum funds to raise)
@@ -28,10 +70,24 @@ pub struct Presale{
     pub start_time: u64,
     // End time of presale
     pub end_time: u64,
-    // Status of softcapped
-    pub is_soft_capped: bool,
-    // Status of hardcapped
-    pub is_hard_capped: bool,
+    // Final auction price, recorded when a Dutch auction stops or a batch auction is settled
+    pub clearing_price: u64,
+    // Release schedule applied to purchased tokens
+    pub vesting: VestingSchedule,
+    // Oracle used to price native SOL payments, SOL is not accepted when unset
+    pub sol_payment: Option<SolPriceFeed>,
+    // Lamports raised through SOL payments
+    pub sol_raised_amount: u64,
+    // Per-purchase and per-wallet contribution limits
+    pub limits: PurchaseLimits,
+    // Merkle root of allowlisted buyers, open sale when unset
+    pub whitelist_root: Option<[u8; 32]>,
+    // Commission paid to referrers, no referral program when unset
+    pub referral: Option<ReferralConfig>,
+    // Bonus tokens credited to referrers, set aside from the deposit
+    pub referral_token_amount: u64,
+    // Tokens credited to buyers and referrers and not claimed yet
+    pub owed_token_amount: u64,
     // PDA bump
     pub bump: u8
 }
@@ -40,7 +96,900 @@ pub struct Presale{
 #[derive(InitSpace)]
 pub struct Level {
     pub token_amount: u64,  // Total tokens available in this level (in lamports)
-    pub price: u64,         // Price per whole token in USDT lamports
-    pub soft_cap: u64,      // Soft cap for this level in USDT lamports
+    pub price: u64,         // Price per whole token, `PRICE_DECIMALS` fixed point
+    pub soft_cap: u64,      // Value to raise before the level counts towards the softcap, `PRICE_DECIMALS` fixed point
     pub tokens_sold: u64,   // Tokens sold in this level (in lamports)
-}
\ No newline at end of file
+    pub raised: u64,        // Value raised in this level at its price, `PRICE_DECIMALS` fixed point
+}
+
+// A level as configured, its sale progress starts at zero
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct LevelConfig {
+    pub token_amount: u64,
+    pub price: u64,
+    pub soft_cap: u64,
+}
+
+impl From<LevelConfig> for Level {
+    fn from(config: LevelConfig) -> Self {
+        Level { token_amount: config.token_amount, price: config.price, soft_cap: config.soft_cap, tokens_sold: 0, raised: 0 }
+    }
+}
+
+impl Level {
+    // Whether the level is configured as `config`, whatever it sold so far
+    pub fn matches(&self, config: &LevelConfig) -> bool {
+        self.token_amount == config.token_amount && self.price == config.price && self.soft_cap == config.soft_cap
+    }
+
+    // A level only counts towards the sale's softcap once it raised its own soft cap, or sold
+    // out below it
+    pub fn is_filled(&self) -> bool {
+        self.raised >= self.soft_cap || self.tokens_sold >= self.token_amount
+    }
+}
+
+// Tokens bought from a single level within one purchase
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct LevelFill {
+    pub level: u8,
+    pub tokens: u64,
+    pub cost: u64,
+}
+
+// How a purchase behaves when the available levels cannot absorb the whole payment
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum FillMode {
+    FillOrKill,     // Fail unless the full payment is spent (or the hardcap is reached)
+    PartialFill,    // Buy what is available and charge only the amount spent
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+#[derive(InitSpace)]
+pub struct QuoteMint {
+    pub mint: Pubkey,           // Stablecoin mint, paid into the presale's ATA for it
+    pub raised_amount: u64,     // Quote raised in this currency
+    pub reserved_amount: u64,   // Quote held for buyers, see `Presale::reserved_quote`
+    pub enabled: bool,          // Whether new purchases may pay in this currency
+    pub decimals: u8,           // Mint decimals, see `Presale::primary_quote_amount`
+}
+
+impl QuoteMint {
+    pub fn new(mint: Pubkey, decimals: u8) -> Self {
+        Self { mint, raised_amount: 0, reserved_amount: 0, enabled: true, decimals }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+#[derive(InitSpace)]
+pub struct SolPriceFeed {
+    pub price_feed: Pubkey,         // Pyth-style SOL/USD price account
+    pub max_price_age: u64,         // Maximum age of the price in seconds
+    pub max_confidence_bps: u16,    // Maximum confidence interval relative to the price
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+#[derive(InitSpace)]
+pub struct VestingSchedule {
+    pub tge_bps: u16,           // Share unlocked at TGE (presale end), in basis points
+    pub cliff_duration: u64,    // Seconds after TGE before linear unlocking starts
+    pub vesting_duration: u64,  // Seconds over which the remainder unlocks linearly
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+#[derive(InitSpace)]
+pub struct PresaleRoles {
+    pub operator: Pubkey,  // Starts, ends, pauses and resumes the sale and edits levels
+    pub treasurer: Pubkey, // The only key able to withdraw raised funds and unsold tokens
+    pub pauser: Pubkey,    // Can pause the sale and nothing else
+}
+
+impl PresaleRoles {
+    pub fn new(admin: Pubkey) -> Self {
+        Self { operator: admin, treasurer: admin, pauser: admin }
+    }
+
+    pub fn can_pause(&self, key: &Pubkey) -> bool {
+        self.operator == *key || self.pauser == *key
+    }
+
+    pub fn set(&mut self, role: PresaleRole, key: Pubkey) {
+        match role {
+            PresaleRole::Operator => self.operator = key,
+            PresaleRole::Treasurer => self.treasurer = key,
+            PresaleRole::Pauser => self.pauser = key,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PresaleRole {
+    Operator,
+    Treasurer,
+    Pauser,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+#[derive(InitSpace)]
+pub struct PurchaseLimits {
+    pub min_purchase: u64,          // Minimum quote spent per purchase in primary mint units, 0 for none
+    pub max_per_wallet: u64,        // Maximum cumulative quote per wallet in primary mint units, 0 for none
+    pub max_tokens_per_wallet: u64, // Maximum cumulative tokens per wallet, 0 for none
+}
+
+impl PurchaseLimits {
+    // Checks a purchase against the limits given the wallet's running totals including it
+    pub fn check(&self, amount_spent: u64, wallet_quote_amount: u64, wallet_token_amount: u64) -> Result<()> {
+        require!(amount_spent >= self.min_purchase, PresaleError::BelowMinimumPurchase);
+        require!(
+            self.max_per_wallet == 0 || wallet_quote_amount <= self.max_per_wallet,
+            PresaleError::WalletLimitExceeded
+        );
+        require!(
+            self.max_tokens_per_wallet == 0 || wallet_token_amount <= self.max_tokens_per_wallet,
+            PresaleError::WalletTokenLimitExceeded
+        );
+        Ok(())
+    }
+}
+
+impl VestingSchedule {
+    pub const MAX_BPS: u16 = 10_000;
+
+    pub fn is_valid(&self) -> bool {
+        self.tge_bps <= Self::MAX_BPS
+    }
+}
+
+impl Presale {
+    // Account size with `level_count` levels
+    pub fn space(level_count: usize) -> usize {
+        8 + Self::INIT_SPACE + level_count * Level::INIT_SPACE
+    }
+
+    // Configuration invariants, checked on creation and after every update
+    pub fn validate(&self, now: u64) -> Result<()> {
+        require!(self.start_time < self.end_time, PresaleError::InvalidTimeRange);
+        require!(self.end_time > now, PresaleError::EndTimeInPast);
+        require!(self.hardcap_amount > 0, PresaleError::ZeroHardCap);
+        require!(self.softcap_amount <= self.hardcap_amount, PresaleError::SoftCapAboveHardCap);
+        require!(self.vesting.is_valid(), PresaleError::InvalidVestingSchedule);
+        require!(self.levels.len() <= MAX_LEVELS, PresaleError::TooManyLevels);
+
+        match self.sale_mode {
+            SaleMode::Tiered => {
+                // Levels without tokens are unused slots and may have any price. A level's soft cap
+                // must be raisable from the part of it the hardcap lets the sale reach
+                let mut tokens_before = 0_u64;
+                for level in self.levels.iter().filter(|level| level.token_amount > 0) {
+                    require!(level.price > 0, PresaleError::ZeroLevelPrice);
+                    let reachable = level.token_amount.min(self.hardcap_amount.saturating_sub(tokens_before));
+                    require!(
+                        level.soft_cap <= self.token_value(reachable, level.price)?,
+                        PresaleError::LevelSoftCapUnreachable
+                    );
+                    tokens_before = tokens_before.saturating_add(level.token_amount);
+                }
+                let level_total = self.tokens_for_sale()?;
+                require!(level_total > 0, PresaleError::NoLevelsConfigured);
+                require!(self.hardcap_amount <= level_total, PresaleError::HardCapExceedsLevels);
+            }
+            // Caps are quote amounts, tokens are priced by the total committed
+            SaleMode::Overflow { token_amount } => {
+                require!(self.levels.is_empty(), PresaleError::InvalidSaleMode);
+                require!(token_amount > 0, PresaleError::InvalidSaleMode);
+            }
+            SaleMode::DutchAuction { token_amount, start_price, floor_price, .. } => {
+                require!(self.levels.is_empty(), PresaleError::InvalidSaleMode);
+                require!(floor_price > 0 && floor_price <= start_price, PresaleError::InvalidSaleMode);
+                require!(self.hardcap_amount <= token_amount, PresaleError::HardCapExceedsLevels);
+            }
+            SaleMode::BondingCurve { token_amount, base_price, shape } => {
+                require!(self.levels.is_empty(), PresaleError::InvalidSaleMode);
+                require!(base_price > 0, PresaleError::InvalidSaleMode);
+                match shape {
+                    CurveShape::Linear { end_price } => require!(end_price >= base_price, PresaleError::InvalidSaleMode),
+                    CurveShape::Exponential { doubling_amount } => require!(
+                        doubling_amount > 0 && token_amount / doubling_amount < MAX_CURVE_DOUBLINGS,
+                        PresaleError::InvalidSaleMode
+                    ),
+                }
+                require!(self.hardcap_amount <= token_amount, PresaleError::HardCapExceedsLevels);
+                // The whole curve must be priceable
+                self.curve_price(token_amount)?;
+            }
+            // Levels are the price ticks bids are placed at, cheapest first
+            SaleMode::BatchAuction { token_amount } => {
+                require!(!self.levels.is_empty(), PresaleError::NoLevelsConfigured);
+                require!(self.levels[0].price > 0, PresaleError::ZeroLevelPrice);
+                require!(
+                    self.levels.iter().all(|level| level.token_amount == 0 && level.soft_cap == 0)
+                        && self.levels.windows(2).all(|ticks| ticks[0].price < ticks[1].price),
+                    PresaleError::InvalidPriceTicks
+                );
+                require!(self.hardcap_amount <= token_amount, PresaleError::HardCapExceedsLevels);
+            }
+        }
+        // Tokens added to a running sale must already be in the vault, before launch the
+        // deposit is checked when the sale opens, see `is_funded`
+        if !matches!(self.status, PresaleStatus::Draft | PresaleStatus::Scheduled) {
+            require!(self.deposit_needed()? <= self.deposit_token_amount, PresaleError::LevelTotalExceedsDeposit);
+        }
+
+        Ok(())
+    }
+
+    // Whether the vault holds every token the sale can sell
+    pub fn is_funded(&self) -> bool {
+        self.deposit_needed().is_ok_and(|tokens| tokens <= self.deposit_token_amount)
+    }
+
+    // Tokens for sale plus the referral bonuses already set aside from the deposit
+    fn deposit_needed(&self) -> Result<u64> {
+        self.tokens_for_sale()?
+            .checked_add(self.referral_token_amount)
+            .ok_or(PresaleError::MathOverflow.into())
+    }
+
+    // Slot of `mint` in `quote_mints`, also used to index `UserInfo::quote_amounts`
+    pub fn quote_index(&self, mint: &Pubkey) -> Result<usize> {
+        self.quote_mints
+            .iter()
+            .position(|quote| quote.mint == *mint)
+            .ok_or(PresaleError::UnsupportedQuoteMint.into())
+    }
+
+    // `amount` base units of the `quote_index` mint in base units of the primary quote mint,
+    // rounded up. Buyer totals, purchase limits and allocations add up purchases in any quote
+    // mint, so they are all kept in the primary mint's units
+    pub fn primary_quote_amount(&self, quote_index: usize, amount: u64) -> Result<u64> {
+        let primary_scale = pow10(self.quote_mints[0].decimals as u32)?;
+        let quote_scale = pow10(self.quote_mints[quote_index].decimals as u32)?;
+        (amount as u128)
+            .checked_mul(primary_scale)
+            .map(|x| x.div_ceil(quote_scale))
+            .and_then(|x| u64::try_from(x).ok())
+            .ok_or(PresaleError::CalculationOverflow.into())
+    }
+
+    // Rounding policy: tokens are rounded down and their cost is rounded up, so the presale never
+    // sells a token unit below its price and a buyer never pays for a unit they do not receive.
+
+    // Tokens `quote` base units of a `quote_decimals` mint buy at `price`, rounded down
+    pub fn tokens_for_quote(&self, quote: u64, price: u64, quote_decimals: u8) -> Result<u64> {
+        // tokens = quote * 10^token_decimals * 10^PRICE_DECIMALS / (price * 10^quote_decimals)
+        let numerator = (quote as u128)
+            .checked_mul(pow10(self.token_decimals as u32 + PRICE_DECIMALS)?);
+        let denominator = (price as u128).checked_mul(pow10(quote_decimals as u32)?);
+        numerator
+            .zip(denominator)
+            .and_then(|(numerator, denominator)| numerator.checked_div(denominator))
+            .and_then(|x| u64::try_from(x).ok())
+            .ok_or(PresaleError::CalculationOverflow.into())
+    }
+
+    // Quote base units of a `quote_decimals` mint owed for `tokens` at `price`, rounded up
+    pub fn quote_for_tokens(&self, tokens: u64, price: u64, quote_decimals: u8) -> Result<u64> {
+        let quote_scale = pow10(quote_decimals as u32)?;
+        let value_scale = pow10(self.token_decimals as u32 + PRICE_DECIMALS)?;
+        (tokens as u128)
+            .checked_mul(price as u128)
+            .and_then(|x| x.checked_mul(quote_scale))
+            .map(|x| x.div_ceil(value_scale))
+            .and_then(|x| u64::try_from(x).ok())
+            .ok_or(PresaleError::CalculationOverflow.into())
+    }
+
+    // Value of `tokens` at `price` in `PRICE_DECIMALS` fixed point, rounded down
+    pub fn token_value(&self, tokens: u64, price: u64) -> Result<u64> {
+        let scale = pow10(self.token_decimals as u32)?;
+        (tokens as u128)
+            .checked_mul(price as u128)
+            .map(|x| x / scale)
+            .and_then(|x| u64::try_from(x).ok())
+            .ok_or(PresaleError::CalculationOverflow.into())
+    }
+
+    // Walks the levels from `current_level` up to `max_level` (inclusive), selling tokens for up
+    // to `payment` base units of a `quote_decimals` mint. Returns the tokens bought, the part of
+    // the payment that could not be spent and the per-level breakdown.
+    pub fn fill_levels(&mut self, payment: u64, quote_decimals: u8, max_level: Option<u8>) -> Result<(u64, u64, Vec<LevelFill>)> {
+        let mut remaining_payment = payment;
+        let mut total_tokens_bought = 0_u64;
+        let mut level_fills = Vec::new();
+        let level_count = max_level.map_or(self.levels.len(), |level| (level as usize + 1).min(self.levels.len()));
+
+        // Continue buying until payment is exhausted or all levels up to `max_level` are sold out
+        while remaining_payment > 0 && (self.current_level as usize) < level_count {
+            let current_level_index = self.current_level as usize;
+            
+            // Read current level data
+            let level_token_amount = self.levels[current_level_index].token_amount;
+            let level_tokens_sold = self.levels[current_level_index].tokens_sold;
+            let level_price = self.levels[current_level_index].price;
+            let tokens_remaining_in_level = level_token_amount - level_tokens_sold;
+            
+            msg!(
+                "Level {} status: total_tokens={}, sold={}, remaining={}, price={}",
+                current_level_index,
+                level_token_amount,
+                level_tokens_sold,
+                tokens_remaining_in_level,
+                level_price
+            );
+            
+            // If current level is exhausted, move to next level
+            if tokens_remaining_in_level == 0 {
+                msg!("Level {} exhausted, moving to next level", current_level_index);
+                self.current_level += 1;
+                continue;
+            }
+
+            // Calculate how many tokens can be bought with remaining payment at current price
+            let tokens_can_afford = self.tokens_for_quote(remaining_payment, level_price, quote_decimals)?;
+            
+            // Take minimum of what can be afforded, what's available in this level and what the
+            // hardcap leaves
+            let tokens_to_buy = tokens_can_afford
+                .min(tokens_remaining_in_level)
+                .min(self.hardcap_amount.saturating_sub(self.sold_token_amount));
+
+            // Remaining payment is dust below the price of one token unit
+            if tokens_to_buy == 0 {
+                break;
+            }
+            
+            // Calculate exact cost for these tokens, never more than `remaining_payment`
+            let cost_for_tokens = self.quote_for_tokens(tokens_to_buy, level_price, quote_decimals)?;
+
+            msg!(
+                "Purchase calculation: can_afford={}, will_buy={}, cost={}, remaining_payment={}",
+                tokens_can_afford,
+                tokens_to_buy,
+                cost_for_tokens,
+                remaining_payment
+            );
+
+            self.sell_from_level(current_level_index, tokens_to_buy)?;
+            level_fills.push(LevelFill { level: current_level_index as u8, tokens: tokens_to_buy, cost: cost_for_tokens });
+            total_tokens_bought += tokens_to_buy;
+
+            remaining_payment -= cost_for_tokens;
+
+            msg!(
+                "After purchase: level_tokens_sold={}, total_sold={}, tokens_bought_this_tx={}, remaining_payment={}",
+                self.levels[current_level_index].tokens_sold,
+                self.sold_token_amount,
+                total_tokens_bought,
+                remaining_payment
+            );
+
+            // If current level is now exhausted, move to next level for next iteration
+            if self.levels[current_level_index].tokens_sold >= level_token_amount {
+                msg!("Level {} completed, moving to next level", current_level_index);
+                self.current_level += 1;
+            }
+
+            // If we've hit hardcap, break out of loop
+            if self.is_hard_capped() {
+                break;
+            }
+        }
+
+        Ok((total_tokens_bought, remaining_payment, level_fills))
+    }
+
+    // Buys exactly `token_amount` tokens walking the levels from `current_level` up to `max_level`
+    // (inclusive), paying in a `quote_decimals` mint. Each level's cost is rounded up.
+    // Returns the total cost and the per-level breakdown.
+    pub fn fill_levels_exact_out(&mut self, token_amount: u64, quote_decimals: u8, max_level: Option<u8>) -> Result<(u64, Vec<LevelFill>)> {
+        let mut remaining_tokens = token_amount;
+        let mut total_cost = 0_u64;
+        let mut level_fills = Vec::new();
+        let level_count = max_level.map_or(self.levels.len(), |level| (level as usize + 1).min(self.levels.len()));
+
+        while remaining_tokens > 0 && (self.current_level as usize) < level_count {
+            let current_level_index = self.current_level as usize;
+            let level = self.levels[current_level_index];
+            let tokens_remaining_in_level = level.token_amount - level.tokens_sold;
+
+            if tokens_remaining_in_level == 0 {
+                self.current_level += 1;
+                continue;
+            }
+
+            let tokens_to_buy = remaining_tokens
+                .min(tokens_remaining_in_level)
+                .min(self.hardcap_amount.saturating_sub(self.sold_token_amount));
+            if tokens_to_buy == 0 {
+                break;
+            }
+            let cost_for_tokens = self.quote_for_tokens(tokens_to_buy, level.price, quote_decimals)?;
+
+            msg!(
+                "Level {} exact purchase: will_buy={}, cost={}, price={}",
+                current_level_index,
+                tokens_to_buy,
+                cost_for_tokens,
+                level.price
+            );
+
+            self.sell_from_level(current_level_index, tokens_to_buy)?;
+            level_fills.push(LevelFill { level: current_level_index as u8, tokens: tokens_to_buy, cost: cost_for_tokens });
+            remaining_tokens -= tokens_to_buy;
+            total_cost = total_cost
+                .checked_add(cost_for_tokens)
+                .ok_or(PresaleError::CalculationOverflow)?;
+
+            if self.levels[current_level_index].tokens_sold >= level.token_amount {
+                self.current_level += 1;
+            }
+        }
+
+        if remaining_tokens > 0 {
+            msg!("Tokens unfilled: {} (all levels exhausted)", remaining_tokens);
+            if max_level.is_some_and(|level| self.current_level > level) {
+                return Err(PresaleError::MaxLevelExceeded.into());
+            }
+            require!(!self.is_hard_capped(), PresaleError::HardCapped);
+            return Err(PresaleError::ExceedsDepositAmount.into());
+        }
+
+        Ok((total_cost, level_fills))
+    }
+
+    // Records `tokens` sold from level `index` against the hardcap and the deposited tokens
+    fn sell_from_level(&mut self, index: usize, tokens: u64) -> Result<()> {
+        self.sell_tokens(tokens)?;
+
+        // Raised value is tracked at the level price so tiers paid in different quote mints add up
+        let value = self.token_value(tokens, self.levels[index].price)?;
+
+        // Update the level state
+        let level = &mut self.levels[index];
+        level.tokens_sold += tokens;
+        level.raised = level.raised
+            .checked_add(value)
+            .ok_or(PresaleError::MathOverflow)?;
+
+        Ok(())
+    }
+
+    // Records `tokens` sold against the hardcap and the deposited tokens
+    pub(crate) fn sell_tokens(&mut self, tokens: u64) -> Result<()> {
+        let sold_token_amount = self.sold_token_amount
+            .checked_add(tokens)
+            .ok_or(PresaleError::MathOverflow)?;
+
+        // Verify we don't exceed hardcap
+        require!(sold_token_amount <= self.hardcap_amount, PresaleError::HardCapped);
+
+        // Verify we don't exceed deposited tokens
+        require!(sold_token_amount <= self.deposit_token_amount, PresaleError::ExceedsDepositAmount);
+
+        self.sold_token_amount = sold_token_amount;
+
+        Ok(())
+    }
+
+    // Records `tokens` credited to a buyer or referrer, paid out by their claims
+    pub(crate) fn owe_tokens(&mut self, tokens: u64) -> Result<()> {
+        self.owed_token_amount = self.owed_token_amount
+            .checked_add(tokens)
+            .ok_or(PresaleError::MathOverflow)?;
+        Ok(())
+    }
+
+    // Records `tokens` claimed out of the vault
+    pub(crate) fn pay_owed_tokens(&mut self, tokens: u64) -> Result<()> {
+        self.owed_token_amount = self.owed_token_amount
+            .checked_sub(tokens)
+            .ok_or(PresaleError::MathOverflow)?;
+        Ok(())
+    }
+
+    // Decides whether a fill that left `remaining_payment` unspent goes through. Fill-or-kill only
+    // accepts leftovers once the hardcap is hit, partial fills charge just what was spent.
+    pub fn check_fill(&self, tokens_bought: u64, remaining_payment: u64, max_level: Option<u8>, fill_mode: FillMode) -> Result<()> {
+        if remaining_payment == 0 || self.is_hard_capped() {
+            return Ok(());
+        }
+
+        msg!("Payment remaining: {} (all levels exhausted or insufficient tokens)", remaining_payment);
+        if fill_mode == FillMode::PartialFill {
+            require!(tokens_bought > 0, PresaleError::NoTokensAvailable);
+            return Ok(());
+        }
+        if max_level.is_some_and(|level| self.current_level > level) {
+            return Err(PresaleError::MaxLevelExceeded.into());
+        }
+        Err(PresaleError::ExactPaymentRequired.into())
+    }
+
+    // Private rounds only accept buyers proven against the whitelist root
+    pub fn check_whitelist(&self, buyer: &Pubkey, max_allocation: u64, proof: &[[u8; 32]]) -> Result<()> {
+        if let Some(root) = self.whitelist_root {
+            let leaf = whitelist_leaf(buyer, max_allocation);
+            require!(verify_proof(proof, &root, leaf), PresaleError::NotWhitelisted);
+        }
+        Ok(())
+    }
+
+    // Checks a purchase against the whitelisted allocation and the configured limits
+    pub fn check_purchase_limits(&self, user: &UserInfo, amount_spent: u64, tokens_bought: u64, max_allocation: u64) -> Result<()> {
+        let total_spent = user.buy_quote_amount
+            .checked_add(amount_spent)
+            .ok_or(PresaleError::MathOverflow)?;
+        let total_tokens = user.buy_token_amount
+            .checked_add(tokens_bought)
+            .ok_or(PresaleError::MathOverflow)?;
+
+        // Allocation encoded in the whitelist leaf caps the buyer's total spend
+        if self.whitelist_root.is_some() {
+            require!(total_spent <= max_allocation, PresaleError::AllocationExceeded);
+        }
+
+        self.limits.check(amount_spent, total_spent, total_tokens)
+    }
+
+    // Amount of `total` purchased tokens unlocked at `now`. Vesting starts at `end_time`,
+    // releases `tge_bps` immediately, then the rest linearly once the cliff has passed.
+    pub fn vested_amount(&self, total: u64, now: u64) -> Result<u64> {
+        if now < self.end_time {
+            return Ok(0);
+        }
+
+        let vesting = &self.vesting;
+        let tge_amount = (total as u128)
+            .checked_mul(vesting.tge_bps as u128)
+            .and_then(|x| x.checked_div(VestingSchedule::MAX_BPS as u128))
+            .ok_or(PresaleError::MathOverflow)? as u64;
+
+        let cliff_end = self.end_time
+            .checked_add(vesting.cliff_duration)
+            .ok_or(PresaleError::MathOverflow)?;
+        if now < cliff_end {
+            return Ok(tge_amount);
+        }
+        if vesting.vesting_duration == 0 {
+            return Ok(total);
+        }
+
+        let elapsed = (now - cliff_end).min(vesting.vesting_duration);
+        let linear_amount = ((total - tge_amount) as u128)
+            .checked_mul(elapsed as u128)
+            .and_then(|x| x.checked_div(vesting.vesting_duration as u128))
+            .ok_or(PresaleError::MathOverflow)? as u64;
+
+        Ok(tge_amount + linear_amount)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::testing::{level, presale, DECIMALS, UNIT};
+
+    // 1,000 tokens at 0.01, then 1,000 tokens at 0.02
+    fn two_levels(hardcap_amount: u64, second_soft_cap: u64) -> Presale {
+        let levels = vec![level(1_000 * UNIT, 10_000, 0), level(1_000 * UNIT, 20_000, second_soft_cap)];
+        presale(SaleMode::Tiered, levels, 0, hardcap_amount)
+    }
+
+    #[test]
+    fn fill_levels_moves_to_the_next_level_once_one_sells_out() {
+        let mut presale = two_levels(2_000 * UNIT, 0);
+
+        // 10 buys out the first level, the other 5 buys 250 tokens at 0.02
+        let (tokens, remaining, fills) = presale.fill_levels(15 * UNIT, DECIMALS, None).unwrap();
+
+        assert_eq!(tokens, 1_250 * UNIT);
+        assert_eq!(remaining, 0);
+        let fills: Vec<_> = fills.iter().map(|fill| (fill.level, fill.tokens, fill.cost)).collect();
+        assert_eq!(fills, vec![(0, 1_000 * UNIT, 10 * UNIT), (1, 250 * UNIT, 5 * UNIT)]);
+        assert_eq!(presale.current_level, 1);
+        assert_eq!(presale.sold_token_amount, 1_250 * UNIT);
+        assert_eq!(presale.levels[0].raised, 10 * UNIT);
+        assert_eq!(presale.levels[1].raised, 5 * UNIT);
+    }
+
+    #[test]
+    fn fill_levels_stops_at_max_level() {
+        let mut presale = two_levels(2_000 * UNIT, 0);
+
+        let (tokens, remaining, _) = presale.fill_levels(15 * UNIT, DECIMALS, Some(0)).unwrap();
+
+        assert_eq!(tokens, 1_000 * UNIT);
+        assert_eq!(remaining, 5 * UNIT);
+        assert_eq!(presale.levels[1].tokens_sold, 0);
+    }
+
+    #[test]
+    fn fill_levels_stops_at_the_hardcap() {
+        let mut presale = two_levels(1_500 * UNIT, 0);
+
+        let (tokens, remaining, _) = presale.fill_levels(30 * UNIT, DECIMALS, None).unwrap();
+
+        assert_eq!(tokens, 1_500 * UNIT);
+        assert_eq!(remaining, 10 * UNIT);
+        assert!(presale.is_hard_capped());
+    }
+
+    #[test]
+    fn fill_or_kill_reports_max_level_when_the_cap_level_is_left_unspent() {
+        let mut presale = two_levels(2_000 * UNIT, 0);
+
+        let (tokens, remaining, _) = presale.fill_levels(15 * UNIT, DECIMALS, Some(0)).unwrap();
+
+        assert_eq!(
+            presale.check_fill(tokens, remaining, Some(0), FillMode::FillOrKill).unwrap_err(),
+            PresaleError::MaxLevelExceeded.into()
+        );
+    }
+
+    #[test]
+    fn exact_out_fails_past_max_level() {
+        let mut presale = two_levels(2_000 * UNIT, 0);
+
+        assert_eq!(
+            presale.fill_levels_exact_out(1_000 * UNIT + 1, DECIMALS, Some(0)).err().unwrap(),
+            PresaleError::MaxLevelExceeded.into()
+        );
+        // A failed instruction reverts, start over from a fresh sale
+        let mut presale = two_levels(2_000 * UNIT, 0);
+        assert!(presale.fill_levels_exact_out(1_000 * UNIT, DECIMALS, Some(0)).is_ok());
+    }
+
+    #[test]
+    fn fill_or_kill_rejects_a_payment_that_only_partly_fills() {
+        // One unit costs 1.5 quote units, 4 buys two of them for 3
+        let mut presale = presale(SaleMode::Tiered, vec![level(1_000 * UNIT, 1_500_000, 0)], 0, 1_000 * UNIT);
+
+        let (tokens, remaining, _) = presale.fill_levels(4, DECIMALS, None).unwrap();
+
+        assert_eq!((tokens, remaining), (2, 1));
+        assert_eq!(
+            presale.check_fill(tokens, remaining, None, FillMode::FillOrKill).unwrap_err(),
+            PresaleError::ExactPaymentRequired.into()
+        );
+        assert!(presale.check_fill(tokens, remaining, None, FillMode::PartialFill).is_ok());
+    }
+
+    #[test]
+    fn partial_fill_takes_what_is_available_and_needs_some_tokens() {
+        let mut presale = two_levels(2_000 * UNIT, 0);
+
+        let (tokens, remaining, _) = presale.fill_levels(15 * UNIT, DECIMALS, Some(0)).unwrap();
+        assert!(presale.check_fill(tokens, remaining, Some(0), FillMode::PartialFill).is_ok());
+        assert_eq!(15 * UNIT - remaining, 10 * UNIT);
+
+        // Level 0 is sold out, nothing is left below the cap
+        let (tokens, remaining, _) = presale.fill_levels(5 * UNIT, DECIMALS, Some(0)).unwrap();
+        assert_eq!((tokens, remaining), (0, 5 * UNIT));
+        assert_eq!(
+            presale.check_fill(tokens, remaining, Some(0), FillMode::PartialFill).unwrap_err(),
+            PresaleError::NoTokensAvailable.into()
+        );
+    }
+
+    #[test]
+    fn exact_out_on_a_nearly_sold_out_sale() {
+        // 1,950 of 2,000 sold
+        let nearly_sold_out = || {
+            let mut presale = two_levels(2_000 * UNIT, 0);
+            presale.fill_levels(29 * UNIT, DECIMALS, None).unwrap();
+            presale
+        };
+
+        assert_eq!(
+            nearly_sold_out().fill_levels_exact_out(51 * UNIT, DECIMALS, None).err().unwrap(),
+            PresaleError::HardCapped.into()
+        );
+
+        let mut presale = nearly_sold_out();
+        let (cost, _) = presale.fill_levels_exact_out(50 * UNIT, DECIMALS, None).unwrap();
+        assert_eq!(cost, UNIT);
+        assert!(presale.is_hard_capped());
+    }
+
+    #[test]
+    fn level_config_is_the_configured_part_of_a_level() {
+        let config = LevelConfig { token_amount: 1_000 * UNIT, price: 10_000, soft_cap: UNIT };
+        let mut data = Vec::new();
+        config.serialize(&mut data).unwrap();
+        assert_eq!(data.len(), 24);
+
+        let mut level = Level::from(config);
+        assert_eq!((level.tokens_sold, level.raised), (0, 0));
+        level.tokens_sold = UNIT;
+        assert!(level.matches(&config));
+        assert!(!level.matches(&LevelConfig { soft_cap: 0, ..config }));
+    }
+
+    #[test]
+    fn fill_levels_walks_every_level_of_a_long_sale() {
+        // 100 tokens per level, each priced 0.01 above the last
+        let levels: Vec<_> = (1..=MAX_LEVELS as u64).map(|step| level(100 * UNIT, step * 10_000, 0)).collect();
+        let total = 100 * UNIT * MAX_LEVELS as u64;
+        let mut sale = presale(SaleMode::Tiered, levels.clone(), 0, total);
+        assert!(sale.validate(0).is_ok());
+
+        // 1 + 2 + ... + 32 for 100 tokens each
+        let cost = UNIT * (1..=MAX_LEVELS as u64).sum::<u64>();
+        let (tokens, remaining, fills) = sale.fill_levels(cost, DECIMALS, None).unwrap();
+        assert_eq!((tokens, remaining, fills.len()), (total, 0, MAX_LEVELS));
+        assert_eq!(sale.current_level as usize, MAX_LEVELS);
+
+        let mut too_many = levels;
+        too_many.push(level(100 * UNIT, 330_000, 0));
+        assert_eq!(
+            presale(SaleMode::Tiered, too_many, 0, total).validate(0).unwrap_err(),
+            PresaleError::TooManyLevels.into()
+        );
+    }
+
+    #[test]
+    fn validate_bounds_level_soft_caps_by_the_reachable_part() {
+        // The hardcap leaves 500 tokens of the second level, worth 10 at 0.02
+        assert!(two_levels(1_500 * UNIT, 10 * UNIT).validate(0).is_ok());
+        assert_eq!(
+            two_levels(1_500 * UNIT, 10 * UNIT + 1).validate(0).unwrap_err(),
+            PresaleError::LevelSoftCapUnreachable.into()
+        );
+        assert!(two_levels(2_000 * UNIT, 20 * UNIT).validate(0).is_ok());
+    }
+
+    fn limits(min_purchase: u64, max_per_wallet: u64, max_tokens_per_wallet: u64) -> PurchaseLimits {
+        PurchaseLimits { min_purchase, max_per_wallet, max_tokens_per_wallet }
+    }
+
+    #[test]
+    fn purchase_limits_accept_purchases_exactly_at_each_limit() {
+        assert!(limits(10 * UNIT, 0, 0).check(10 * UNIT, 10 * UNIT, 1_000 * UNIT).is_ok());
+        assert!(limits(0, 50 * UNIT, 0).check(10 * UNIT, 50 * UNIT, 1_000 * UNIT).is_ok());
+        assert!(limits(0, 0, 1_000 * UNIT).check(10 * UNIT, 10 * UNIT, 1_000 * UNIT).is_ok());
+        // Zero disables a limit
+        assert!(limits(0, 0, 0).check(1, u64::MAX, u64::MAX).is_ok());
+    }
+
+    #[test]
+    fn purchase_limits_reject_purchases_past_each_limit() {
+        assert_eq!(
+            limits(10 * UNIT, 0, 0).check(10 * UNIT - 1, 10 * UNIT, 1_000 * UNIT).unwrap_err(),
+            PresaleError::BelowMinimumPurchase.into()
+        );
+        assert_eq!(
+            limits(0, 50 * UNIT, 0).check(10 * UNIT, 50 * UNIT + 1, 1_000 * UNIT).unwrap_err(),
+            PresaleError::WalletLimitExceeded.into()
+        );
+        assert_eq!(
+            limits(0, 0, 1_000 * UNIT).check(10 * UNIT, 10 * UNIT, 1_000 * UNIT + 1).unwrap_err(),
+            PresaleError::WalletTokenLimitExceeded.into()
+        );
+    }
+
+    #[test]
+    fn check_purchase_limits_counts_earlier_purchases() {
+        let mut presale = two_levels(2_000 * UNIT, 0);
+        presale.limits = limits(UNIT, 50 * UNIT, 4_000 * UNIT);
+        let mut user = UserInfo {
+            buyer: Pubkey::default(),
+            buy_quote_amount: 0,
+            quote_amounts: [0; MAX_QUOTE_MINTS],
+            sol_amount: 0,
+            buy_token_amount: 0,
+            has_claimed_token: false,
+            has_claimed_refund: false,
+            buy_time: 0,
+            claim_amount: 0,
+            claim_time: 0,
+            bump: 0,
+        };
+        user.record_purchase(Pubkey::default(), 40 * UNIT, 3_000 * UNIT, 0).unwrap();
+
+        assert!(presale.check_purchase_limits(&user, 10 * UNIT, 1_000 * UNIT, 0).is_ok());
+        assert_eq!(
+            presale.check_purchase_limits(&user, 10 * UNIT + 1, 1_000 * UNIT, 0).unwrap_err(),
+            PresaleError::WalletLimitExceeded.into()
+        );
+        assert_eq!(
+            presale.check_purchase_limits(&user, 10 * UNIT, 1_000 * UNIT + 1, 0).unwrap_err(),
+            PresaleError::WalletTokenLimitExceeded.into()
+        );
+    }
+
+    #[test]
+    fn nine_decimal_token_is_priced_per_whole_token() {
+        // The same two levels with a 9-decimal sale token, paid in the 6-decimal quote mint
+        let token_unit = 1_000_000_000;
+        let levels = vec![level(1_000 * token_unit, 10_000, 0), level(1_000 * token_unit, 20_000, 0)];
+        let mut presale = presale(SaleMode::Tiered, levels, 0, 2_000 * token_unit);
+        presale.token_decimals = 9;
+
+        assert_eq!(presale.tokens_for_quote(5 * UNIT, 10_000, DECIMALS).unwrap(), 500 * token_unit);
+        assert_eq!(presale.quote_for_tokens(500 * token_unit, 10_000, DECIMALS).unwrap(), 5 * UNIT);
+        // One quote unit buys 0.0001 tokens, one token unit still costs a whole quote unit
+        assert_eq!(presale.tokens_for_quote(1, 10_000, DECIMALS).unwrap(), 100_000);
+        assert_eq!(presale.quote_for_tokens(1, 10_000, DECIMALS).unwrap(), 1);
+
+        let (tokens, remaining, fills) = presale.fill_levels(15 * UNIT, DECIMALS, None).unwrap();
+        assert_eq!((tokens, remaining), (1_250 * token_unit, 0));
+        assert_eq!(fills[0].cost, 10 * UNIT);
+        assert_eq!(fills[1].cost, 5 * UNIT);
+        // Raised value does not depend on either mint's decimals
+        assert_eq!(presale.levels[0].raised, 10 * UNIT);
+        assert_eq!(presale.levels[1].raised, 5 * UNIT);
+    }
+
+    #[test]
+    fn quote_mints_with_other_decimals_pay_the_same_price() {
+        let mut presale = two_levels(2_000 * UNIT, 0);
+        presale.quote_mints.push(QuoteMint::new(Pubkey::default(), 9));
+        presale.quote_mints.push(QuoteMint::new(Pubkey::default(), 2));
+
+        let (tokens, _, fills) = presale.fill_levels(15_000_000_000, 9, None).unwrap();
+        assert_eq!(tokens, 1_250 * UNIT);
+        assert_eq!(fills[0].cost + fills[1].cost, 15_000_000_000);
+        assert_eq!(presale.quote_for_tokens(100 * UNIT, 20_000, 2).unwrap(), 200);
+
+        // Limits and buyer totals count in the primary mint's units, rounded up
+        assert_eq!(presale.primary_quote_amount(0, 5 * UNIT).unwrap(), 5 * UNIT);
+        assert_eq!(presale.primary_quote_amount(1, 5_000_000_000).unwrap(), 5 * UNIT);
+        assert_eq!(presale.primary_quote_amount(1, 1).unwrap(), 1);
+        assert_eq!(presale.primary_quote_amount(2, 500).unwrap(), 5 * UNIT);
+    }
+
+    fn vesting(tge_bps: u16, cliff_duration: u64, vesting_duration: u64) -> Presale {
+        let mut presale = two_levels(2_000 * UNIT, 0);
+        presale.vesting = VestingSchedule { tge_bps, cliff_duration, vesting_duration };
+        presale
+    }
+
+    #[test]
+    fn vested_amount_releases_tge_then_linearly_after_the_cliff() {
+        // 25% at TGE, 100s cliff, the other 750 over 400s
+        let presale = vesting(2_500, 100, 400);
+        let total = 1_000 * UNIT;
+        let tge = presale.end_time;
+
+        assert_eq!(presale.vested_amount(total, tge - 1).unwrap(), 0);
+        assert_eq!(presale.vested_amount(total, tge).unwrap(), 250 * UNIT);
+        assert_eq!(presale.vested_amount(total, tge + 99).unwrap(), 250 * UNIT);
+        assert_eq!(presale.vested_amount(total, tge + 100).unwrap(), 250 * UNIT);
+        assert_eq!(presale.vested_amount(total, tge + 300).unwrap(), 625 * UNIT);
+        assert_eq!(presale.vested_amount(total, tge + 500).unwrap(), total);
+        assert_eq!(presale.vested_amount(total, tge + 10_000).unwrap(), total);
+    }
+
+    #[test]
+    fn vested_amount_without_a_tge_share_starts_at_the_cliff() {
+        let presale = vesting(0, 100, 400);
+        let total = 1_000 * UNIT;
+        let tge = presale.end_time;
+
+        assert_eq!(presale.vested_amount(total, tge).unwrap(), 0);
+        assert_eq!(presale.vested_amount(total, tge + 100).unwrap(), 0);
+        assert_eq!(presale.vested_amount(total, tge + 300).unwrap(), 500 * UNIT);
+        assert_eq!(presale.vested_amount(total, tge + 500).unwrap(), total);
+    }
+
+    #[test]
+    fn vested_amount_with_a_full_tge_share_ignores_the_cliff() {
+        let presale = vesting(VestingSchedule::MAX_BPS, 100, 400);
+        let total = 1_000 * UNIT;
+        let tge = presale.end_time;
+
+        assert_eq!(presale.vested_amount(total, tge - 1).unwrap(), 0);
+        assert_eq!(presale.vested_amount(total, tge).unwrap(), total);
+        assert_eq!(presale.vested_amount(total, tge + 300).unwrap(), total);
+    }
+
+    #[test]
+    fn vested_amount_rounds_the_tge_share_down() {
+        // 33.33% of 10 units
+        let presale = vesting(3_333, 0, 400);
+        let tge = presale.end_time;
+
+        assert_eq!(presale.vested_amount(10, tge).unwrap(), 3);
+        assert_eq!(presale.vested_amount(10, tge + 400).unwrap(), 10);
+    }
+}
@@ -0,0 +1,230 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::PresaleError, state::{Presale, SaleMode, PAUSE_BUYS}};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(InitSpace)]
+pub enum PresaleStatus {
+    Draft,      // Created, waiting for the admin to start it
+    Scheduled,  // Starts on its own at `start_time`
+    Live,       // Accepting purchases
+    Paused,     // Purchases halted, resumable
+    Succeeded,  // Ended with the softcap reached, buyers claim tokens
+    Failed,     // Ended below the softcap, buyers claim refunds
+    Cancelled,  // Called off by the admin, buyers claim refunds
+    Finalized,  // Succeeded and unsold tokens returned to the admin
+}
+
+// Admin actions that move a presale between statuses
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PresaleAction {
+    Start,
+    End,
+    Pause,
+    Resume,
+    Cancel,
+    Finalize,
+}
+
+impl Presale {
+    // Overflow sales count the quote committed, tiered sales only the tokens sold in levels that
+    // reached their own soft cap, or every level once the hardcap stopped the sale
+    pub fn is_soft_capped(&self) -> bool {
+        match self.sale_mode {
+            SaleMode::Tiered => {
+                let hard_capped = self.is_hard_capped();
+                let counted_tokens = self.levels
+                    .iter()
+                    .filter(|level| hard_capped || level.is_filled())
+                    .fold(0_u64, |total, level| total.saturating_add(level.tokens_sold));
+                counted_tokens >= self.softcap_amount
+            }
+            SaleMode::Overflow { .. } => self.committed_amount() >= self.softcap_amount,
+            SaleMode::DutchAuction { .. } | SaleMode::BondingCurve { .. } => self.sold_token_amount >= self.softcap_amount,
+            // Every bid fills at the lowest price bid when the hardcap is not covered
+            SaleMode::BatchAuction { .. } => self.total_demand().min(self.hardcap_amount) >= self.softcap_amount,
+        }
+    }
+
+    // Overflow sales and batch auctions accept orders past the hardcap until `end_time`
+    pub fn is_hard_capped(&self) -> bool {
+        !matches!(self.sale_mode, SaleMode::Overflow { .. } | SaleMode::BatchAuction { .. })
+            && self.sold_token_amount >= self.hardcap_amount
+    }
+
+    // Status a sale settles into once it stops taking purchases
+    fn outcome(&self) -> PresaleStatus {
+        if self.is_soft_capped() {
+            PresaleStatus::Succeeded
+        } else {
+            PresaleStatus::Failed
+        }
+    }
+
+    // Status at `now`, including the transitions that happen without an instruction:
+    // a scheduled sale opening at `start_time`, and a running sale ending at `end_time`
+    // or when the hardcap is hit
+    pub fn status_at(&self, now: u64) -> PresaleStatus {
+        match self.status {
+            // An unfunded sale stays closed until its tokens are deposited
+            PresaleStatus::Scheduled if now >= self.start_time && self.is_funded() => {
+                if now >= self.end_time { self.outcome() } else { PresaleStatus::Live }
+            }
+            // The end time of a pause that extends it is only known on resume
+            PresaleStatus::Paused if self.extend_on_resume => PresaleStatus::Paused,
+            PresaleStatus::Live | PresaleStatus::Paused
+                if now >= self.end_time || self.is_hard_capped() => self.outcome(),
+            status => status,
+        }
+    }
+
+    // Stores the status at `now`, every instruction calls this before reading `status`
+    pub fn sync_status(&mut self, now: u64) -> PresaleStatus {
+        let status = self.status_at(now);
+        self.set_status(status, now);
+        status
+    }
+
+    fn set_status(&mut self, next: PresaleStatus, now: u64) {
+        // A sale stopping early (end_presale, hardcap) ends now, vesting starts from here
+        let was_running = matches!(self.status, PresaleStatus::Live | PresaleStatus::Paused);
+        if was_running && matches!(next, PresaleStatus::Succeeded | PresaleStatus::Failed) {
+            // Auctions settle at the price reached when the sale stopped, before the end moves
+            if let Ok(price) = self.auction_price(now) {
+                self.clearing_price = price;
+            }
+            self.end_time = self.end_time.min(now);
+        }
+        self.status = next;
+    }
+
+    // The only place statuses change on request; rejects any edge not listed here
+    pub fn transition(&mut self, action: PresaleAction, now: u64) -> Result<PresaleStatus> {
+        use PresaleStatus::*;
+
+        let next = match (self.sync_status(now), action) {
+            // Scheduled sales open at `start_time` only, see `status_at`
+            (Draft, PresaleAction::Start) if !self.is_funded() => return Err(PresaleError::PresaleNotFunded.into()),
+            (Draft, PresaleAction::Start) => Live,
+            (Live | Paused, PresaleAction::End) => self.outcome(),
+            (Live, PresaleAction::Pause) => Paused,
+            (Paused, PresaleAction::Resume) => Live,
+            (Draft | Scheduled | Live | Paused, PresaleAction::Cancel) => Cancelled,
+            (Succeeded, PresaleAction::Finalize) => Finalized,
+            (Live, PresaleAction::Start) => return Err(PresaleError::AlreadyLive.into()),
+            (Succeeded | Failed | Cancelled | Finalized, _) => return Err(PresaleError::PresaleEnded.into()),
+            _ => return Err(PresaleError::InvalidStatusTransition.into()),
+        };
+
+        msg!("Presale status: {:?} -> {:?}", self.status, next);
+        self.set_status(next, now);
+        Ok(next)
+    }
+
+    // Fails if the admin halted `operation`, one of the `PAUSE_*` bits
+    pub fn require_not_paused(&self, operation: u8) -> Result<()> {
+        require!(self.pause_flags & operation == 0, PresaleError::OperationPaused);
+        Ok(())
+    }
+
+    // Purchases are only taken while live
+    pub fn require_live(&mut self, now: u64) -> Result<()> {
+        match self.sync_status(now) {
+            PresaleStatus::Live => self.require_not_paused(PAUSE_BUYS),
+            PresaleStatus::Scheduled if now >= self.start_time => Err(PresaleError::PresaleNotFunded.into()),
+            PresaleStatus::Draft | PresaleStatus::Scheduled => Err(PresaleError::PresaleNotStarted.into()),
+            PresaleStatus::Paused => Err(PresaleError::PresalePaused.into()),
+            _ => Err(PresaleError::PresaleEnded.into()),
+        }
+    }
+
+    // Token claims and raised funds withdrawals need a successful sale
+    pub fn require_succeeded(&mut self, now: u64) -> Result<()> {
+        match self.sync_status(now) {
+            PresaleStatus::Succeeded | PresaleStatus::Finalized => Ok(()),
+            PresaleStatus::Failed | PresaleStatus::Cancelled => Err(PresaleError::SoftCapNotReached.into()),
+            _ => Err(PresaleError::PresaleNotEnded.into()),
+        }
+    }
+
+    // Refunds need a failed or cancelled sale
+    pub fn require_refundable(&mut self, now: u64) -> Result<()> {
+        match self.sync_status(now) {
+            PresaleStatus::Failed | PresaleStatus::Cancelled => Ok(()),
+            PresaleStatus::Succeeded | PresaleStatus::Finalized => Err(PresaleError::SoftCapReached.into()),
+            _ => Err(PresaleError::PresaleNotEnded.into()),
+        }
+    }
+
+    // The account can only go once the sale is finalized and nothing in the vaults is owed: every
+    // credited token claimed and no quote held for refunds, rebates, bids or commissions. SOL is
+    // only ever owed back by failed or cancelled sales
+    pub fn require_closable(&mut self, now: u64) -> Result<()> {
+        require!(self.sync_status(now) == PresaleStatus::Finalized, PresaleError::PresaleNotFinalized);
+        require!(self.owed_token_amount == 0, PresaleError::TokensStillOwed);
+        require!(
+            self.quote_mints.iter().all(|quote| quote.reserved_amount == 0),
+            PresaleError::QuoteStillReserved
+        );
+        Ok(())
+    }
+
+    pub fn is_ended(&self, now: u64) -> bool {
+        matches!(
+            self.status_at(now),
+            PresaleStatus::Succeeded | PresaleStatus::Failed | PresaleStatus::Cancelled | PresaleStatus::Finalized
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::testing::{level, presale, DECIMALS, END_TIME, UNIT};
+
+    // 1,000 tokens at 0.01, then 1,000 tokens at 0.02 that count once 10 is raised in them
+    fn two_levels(softcap_amount: u64, hardcap_amount: u64) -> Presale {
+        let levels = vec![level(1_000 * UNIT, 10_000, 0), level(1_000 * UNIT, 20_000, 10 * UNIT)];
+        presale(SaleMode::Tiered, levels, softcap_amount, hardcap_amount)
+    }
+
+    #[test]
+    fn level_below_its_soft_cap_does_not_count() {
+        let mut presale = two_levels(1_500 * UNIT, 2_000 * UNIT);
+
+        // 1,250 sold, only the first level's 1,000 count
+        presale.fill_levels(15 * UNIT, DECIMALS, None).unwrap();
+        assert_eq!(presale.status_at(END_TIME), PresaleStatus::Failed);
+
+        // 10 raised in the second level, all 1,750 count
+        presale.fill_levels(5 * UNIT, DECIMALS, None).unwrap();
+        assert_eq!(presale.status_at(END_TIME - 1), PresaleStatus::Live);
+        assert_eq!(presale.status_at(END_TIME), PresaleStatus::Succeeded);
+    }
+
+    #[test]
+    fn sold_out_level_counts_below_its_soft_cap() {
+        // Three units at 1.5 are worth 4, bought one at a time they raise 1 each
+        let levels = vec![level(3, 1_500_000, 4)];
+        let mut presale = presale(SaleMode::Tiered, levels, 3, 3);
+        presale.hardcap_amount = u64::MAX;
+        for _ in 0..3 {
+            presale.fill_levels_exact_out(1, DECIMALS, None).unwrap();
+        }
+
+        assert_eq!(presale.levels[0].raised, 3);
+        assert_eq!(presale.status_at(END_TIME), PresaleStatus::Succeeded);
+    }
+
+    #[test]
+    fn hard_capped_sale_counts_every_level() {
+        let mut presale = two_levels(1_500 * UNIT, 1_500 * UNIT);
+        // Soft cap raised after the hardcap was configured
+        presale.levels[1].soft_cap = 20 * UNIT;
+
+        presale.fill_levels(20 * UNIT, DECIMALS, None).unwrap();
+
+        assert!(presale.is_hard_capped());
+        assert_eq!(presale.status_at(END_TIME - 1), PresaleStatus::Succeeded);
+    }
+}
@@ -0,0 +1,308 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::PresaleError, state::{pow10, Presale, MAX_QUOTE_MINTS, PRICE_DECIMALS}};
+
+// Commission paid to referrers on the purchases they bring in
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(InitSpace)]
+pub struct ReferralConfig {
+    pub commission_bps: u16,        // Share of each referred purchase credited to the referrer, at most `MAX_COMMISSION_BPS`
+    pub payout: CommissionPayout,
+    pub max_commission: u64,        // Commission cap per referrer in payout units, 0 for none
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(InitSpace)]
+pub enum CommissionPayout {
+    Tokens, // Bonus sale tokens out of the deposit beyond the tokens on sale
+    Quote,  // The quote paid, in whichever mint the buyer paid with. Capped by its `PRICE_DECIMALS` value.
+}
+
+impl ReferralConfig {
+    pub const MAX_BPS: u16 = 10_000;
+    // Commissions come out of what buyers paid, keep most of it for the sale
+    pub const MAX_COMMISSION_BPS: u16 = 2_000;
+
+    pub fn is_valid(&self) -> bool {
+        self.commission_bps <= Self::MAX_COMMISSION_BPS
+    }
+
+    fn commission(&self, amount: u64) -> u64 {
+        ((amount as u128) * self.commission_bps as u128 / Self::MAX_BPS as u128) as u64
+    }
+}
+
+// A referrer's running totals in one presale
+#[account]
+#[derive(InitSpace)]
+pub struct Referral {
+    // Presale the referrer is registered in
+    pub presale: Pubkey,
+    // Referrer's address, receives the commissions
+    pub referrer: Pubkey,
+    // Value of the referred purchases, `PRICE_DECIMALS` fixed point
+    pub referred_volume: u64,
+    // Bonus tokens credited so far
+    pub token_commission: u64,
+    // Quote credited and not claimed yet, indexed like `Presale::quote_mints`
+    pub quote_commissions: [u64; MAX_QUOTE_MINTS],
+    // Value of all quote credited so far, `PRICE_DECIMALS` fixed point
+    pub quote_commission_value: u64,
+    // Bonus tokens already claimed
+    pub claimed_tokens: u64,
+    // Quote commission on uniform-price auction purchases as a share of the tokens bought, paid
+    // at the clearing price once it is known, see `Presale::settle_deferred_commission`
+    pub deferred_commission: u64,
+    // Primary quote held for it meanwhile, the commission on the prices paid
+    pub deferred_reserve: u64,
+    // bump
+    pub bump: u8
+}
+
+impl Presale {
+    // Value of `amount` base units of a `quote_decimals` mint, `PRICE_DECIMALS` fixed point
+    fn quote_value(amount: u64, quote_decimals: u8) -> Result<u64> {
+        let value = (amount as u128) * pow10(PRICE_DECIMALS)? / pow10(quote_decimals as u32)?;
+        u64::try_from(value).map_err(|_| PresaleError::MathOverflow.into())
+    }
+
+    // Credits `referral` with the commission on a purchase of `tokens_bought` for `amount_spent`
+    // of the `quote_index` mint. Returns the commission in payout units, after the referrer's cap
+    // and, for bonus tokens, the tokens deposited beyond the sale. Quote commissions in uniform-price
+    // auctions are deferred until the clearing price is known and count as 0 here.
+    pub fn credit_referral(
+        &mut self,
+        referral: &mut Referral,
+        quote_index: usize,
+        quote_decimals: u8,
+        amount_spent: u64,
+        tokens_bought: u64,
+    ) -> Result<u64> {
+        let Some(config) = self.referral else {
+            return Ok(0);
+        };
+        referral.referred_volume = referral.referred_volume
+            .saturating_add(Self::quote_value(amount_spent, quote_decimals)?);
+
+        match config.payout {
+            CommissionPayout::Tokens => {
+                let cap = if config.max_commission == 0 { u64::MAX } else { config.max_commission };
+                let budget = self.deposit_token_amount
+                    .saturating_sub(self.tokens_for_sale()?)
+                    .saturating_sub(self.referral_token_amount);
+                let commission = config.commission(tokens_bought)
+                    .min(cap.saturating_sub(referral.token_commission))
+                    .min(budget);
+
+                referral.token_commission = referral.token_commission
+                    .checked_add(commission)
+                    .ok_or(PresaleError::MathOverflow)?;
+                self.referral_token_amount = self.referral_token_amount
+                    .checked_add(commission)
+                    .ok_or(PresaleError::MathOverflow)?;
+                self.owe_tokens(commission)?;
+                Ok(commission)
+            }
+            // Buyers are only charged the clearing price in the end, the commission on what they
+            // paid now is held back as an upper bound of the one they will owe
+            CommissionPayout::Quote if self.has_rebates() => {
+                let reserve = config.commission(amount_spent);
+                referral.deferred_commission = referral.deferred_commission
+                    .checked_add(config.commission(tokens_bought))
+                    .ok_or(PresaleError::MathOverflow)?;
+                referral.deferred_reserve = referral.deferred_reserve
+                    .checked_add(reserve)
+                    .ok_or(PresaleError::MathOverflow)?;
+                self.quote_mints[0].reserved_amount = self.quote_mints[0].reserved_amount
+                    .checked_add(reserve)
+                    .ok_or(PresaleError::MathOverflow)?;
+                Ok(0)
+            }
+            CommissionPayout::Quote => {
+                let commission = config.commission(amount_spent);
+                self.credit_quote_commission(referral, config.max_commission, quote_index, quote_decimals, commission)
+            }
+        }
+    }
+
+    // Credits `referral` with its deferred commission priced at the clearing price, never more
+    // than was held back for it. The rest of the reserve is released. Still settles after the
+    // referral program is switched off, within the reserve only.
+    pub fn settle_deferred_commission(&mut self, referral: &mut Referral) -> Result<()> {
+        if referral.deferred_reserve == 0 {
+            return Ok(());
+        }
+
+        let quote_decimals = self.quote_mints[0].decimals;
+        let reserve = referral.deferred_reserve;
+        let commission = self
+            .quote_for_tokens(referral.deferred_commission, self.clearing_price, quote_decimals)?
+            .min(reserve);
+        referral.deferred_commission = 0;
+        referral.deferred_reserve = 0;
+        self.quote_mints[0].reserved_amount = self.quote_mints[0].reserved_amount
+            .checked_sub(reserve)
+            .ok_or(PresaleError::MathOverflow)?;
+
+        let max_commission = self.referral.map_or(0, |config| config.max_commission);
+        self.credit_quote_commission(referral, max_commission, 0, quote_decimals, commission)?;
+        Ok(())
+    }
+
+    // Credits `commission` of the `quote_index` mint to `referral` within `max_commission` (0 for
+    // none) and holds it in the vault for them. Returns the commission credited.
+    fn credit_quote_commission(
+        &mut self,
+        referral: &mut Referral,
+        max_commission: u64,
+        quote_index: usize,
+        quote_decimals: u8,
+        mut commission: u64,
+    ) -> Result<u64> {
+        let cap = if max_commission == 0 { u64::MAX } else { max_commission };
+        let remaining_value = cap.saturating_sub(referral.quote_commission_value);
+        if Self::quote_value(commission, quote_decimals)? > remaining_value {
+            // Below the uncapped commission, so it fits in u64
+            commission = ((remaining_value as u128) * pow10(quote_decimals as u32)?
+                / pow10(PRICE_DECIMALS)?) as u64;
+        }
+
+        referral.quote_commissions[quote_index] = referral.quote_commissions[quote_index]
+            .checked_add(commission)
+            .ok_or(PresaleError::MathOverflow)?;
+        referral.quote_commission_value = referral.quote_commission_value
+            .checked_add(Self::quote_value(commission, quote_decimals)?)
+            .ok_or(PresaleError::MathOverflow)?;
+        // Owed to the referrer, the treasurer cannot withdraw it
+        let quote = &mut self.quote_mints[quote_index];
+        quote.reserved_amount = quote.reserved_amount
+            .checked_add(commission)
+            .ok_or(PresaleError::MathOverflow)?;
+        Ok(commission)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{testing::{level, presale, DECIMALS, END_TIME, UNIT}, SaleMode};
+
+    fn referral() -> Referral {
+        Referral {
+            presale: Pubkey::default(),
+            referrer: Pubkey::default(),
+            referred_volume: 0,
+            token_commission: 0,
+            quote_commissions: [0; MAX_QUOTE_MINTS],
+            quote_commission_value: 0,
+            claimed_tokens: 0,
+            deferred_commission: 0,
+            deferred_reserve: 0,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn token_commissions_stay_within_the_deposit() {
+        // 1,000 tokens on sale with 30 more deposited for bonuses
+        let mut presale = presale(SaleMode::Tiered, vec![level(1_000 * UNIT, 10_000, 0)], 0, 1_000 * UNIT);
+        presale.deposit_token_amount += 30 * UNIT;
+        presale.referral = Some(ReferralConfig { commission_bps: 500, payout: CommissionPayout::Tokens, max_commission: 0 });
+        let mut referral = referral();
+
+        // 5% of 400 tokens, then of 400 more with only 10 left in the budget
+        assert_eq!(presale.credit_referral(&mut referral, 0, DECIMALS, 4 * UNIT, 400 * UNIT).unwrap(), 20 * UNIT);
+        assert_eq!(presale.credit_referral(&mut referral, 0, DECIMALS, 4 * UNIT, 400 * UNIT).unwrap(), 10 * UNIT);
+        assert_eq!(presale.credit_referral(&mut referral, 0, DECIMALS, 4 * UNIT, 400 * UNIT).unwrap(), 0);
+        assert_eq!(referral.token_commission, 30 * UNIT);
+        assert_eq!(presale.referral_token_amount, 30 * UNIT);
+        assert_eq!(presale.owed_token_amount, 30 * UNIT);
+        assert_eq!(referral.referred_volume, 12 * UNIT);
+
+        // The bonuses count against the deposit like the tokens on sale
+        assert!(presale.validate(0).is_ok());
+        presale.deposit_token_amount -= 1;
+        assert_eq!(presale.validate(0).unwrap_err(), PresaleError::LevelTotalExceedsDeposit.into());
+    }
+
+    #[test]
+    fn quote_commissions_are_capped_by_value() {
+        let mut presale = presale(SaleMode::Tiered, vec![level(1_000 * UNIT, 10_000, 0)], 0, 1_000 * UNIT);
+        presale.referral = Some(ReferralConfig { commission_bps: 1_000, payout: CommissionPayout::Quote, max_commission: 3 * UNIT });
+        let mut referral = referral();
+
+        assert_eq!(presale.credit_referral(&mut referral, 0, DECIMALS, 20 * UNIT, 0).unwrap(), 2 * UNIT);
+        assert_eq!(presale.credit_referral(&mut referral, 0, DECIMALS, 20 * UNIT, 0).unwrap(), UNIT);
+        assert_eq!(referral.quote_commissions[0], 3 * UNIT);
+        assert_eq!(referral.quote_commission_value, 3 * UNIT);
+        assert_eq!(presale.quote_mints[0].reserved_amount, 3 * UNIT);
+    }
+
+    #[test]
+    fn commission_is_capped_well_below_the_purchase() {
+        let config = |commission_bps| ReferralConfig { commission_bps, payout: CommissionPayout::Quote, max_commission: 0 };
+        assert!(config(ReferralConfig::MAX_COMMISSION_BPS).is_valid());
+        assert!(!config(ReferralConfig::MAX_COMMISSION_BPS + 1).is_valid());
+        assert!(!config(ReferralConfig::MAX_BPS).is_valid());
+    }
+
+    // 200 tokens falling from 0.1 to 0.02, everyone pays the clearing price
+    fn uniform_auction(max_commission: u64) -> Presale {
+        let sale_mode = SaleMode::DutchAuction {
+            token_amount: 200 * UNIT,
+            start_price: 100_000,
+            floor_price: 20_000,
+            step_duration: 0,
+            uniform_price: true,
+        };
+        let mut presale = presale(sale_mode, Vec::new(), 0, 200 * UNIT);
+        presale.referral = Some(ReferralConfig {
+            commission_bps: ReferralConfig::MAX_COMMISSION_BPS,
+            payout: CommissionPayout::Quote,
+            max_commission,
+        });
+        presale
+    }
+
+    #[test]
+    fn uniform_auction_commissions_are_paid_on_the_clearing_price() {
+        let mut presale = uniform_auction(0);
+        let mut referral = referral();
+
+        // 10 buys 100 tokens at 0.1, the commission on it is only held back
+        let (tokens, _, _) = presale.fill_payment(10 * UNIT, DECIMALS, None, 0).unwrap();
+        assert_eq!(presale.credit_referral(&mut referral, 0, DECIMALS, 10 * UNIT, tokens).unwrap(), 0);
+        assert_eq!(referral.deferred_reserve, 2 * UNIT);
+        assert_eq!(presale.quote_mints[0].reserved_amount, 2 * UNIT);
+
+        // Cleared at the 0.02 floor, the buyer is charged 2 and the referrer earns 20% of that
+        presale.sync_status(END_TIME);
+        let rebate = presale.auction_rebate(tokens, 10 * UNIT, DECIMALS).unwrap();
+        presale.settle_deferred_commission(&mut referral).unwrap();
+        assert_eq!(referral.quote_commissions[0], 400_000);
+        assert_eq!(presale.quote_mints[0].reserved_amount, 400_000);
+        assert_eq!((referral.deferred_commission, referral.deferred_reserve), (0, 0));
+        // Together with the rebate it stays within what the buyer paid
+        assert_eq!(rebate, 8 * UNIT);
+        assert!(rebate + referral.quote_commissions[0] <= 10 * UNIT);
+
+        // Settling again credits nothing more
+        presale.settle_deferred_commission(&mut referral).unwrap();
+        assert_eq!(referral.quote_commissions[0], 400_000);
+    }
+
+    #[test]
+    fn deferred_commissions_stay_within_the_referrer_cap() {
+        let mut presale = uniform_auction(300_000);
+        let mut referral = referral();
+
+        let (tokens, _, _) = presale.fill_payment(10 * UNIT, DECIMALS, None, 0).unwrap();
+        presale.credit_referral(&mut referral, 0, DECIMALS, 10 * UNIT, tokens).unwrap();
+        presale.sync_status(END_TIME);
+        presale.settle_deferred_commission(&mut referral).unwrap();
+
+        assert_eq!(referral.quote_commissions[0], 300_000);
+        assert_eq!(referral.quote_commission_value, 300_000);
+        assert_eq!(presale.quote_mints[0].reserved_amount, 300_000);
+    }
+}
@@ -0,0 +1,352 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::PresaleError, state::{CurveShape, LevelFill, Presale}};
+
+// How tokens are priced and allocated
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(InitSpace)]
+pub enum SaleMode {
+    Tiered,                         // First come first served through the price levels
+    Overflow { token_amount: u64 }, // Buyers commit quote, `token_amount` tokens are shared pro rata
+    DutchAuction {                  // Price falls from `start_price` to `floor_price` over the sale
+        token_amount: u64,          // Tokens on sale, capped by `hardcap_amount`
+        start_price: u64,           // Price per whole token at `start_time`, `PRICE_DECIMALS` fixed point
+        floor_price: u64,           // Price per whole token at `end_time`
+        step_duration: u64,         // Seconds between price drops, 0 decays continuously
+        uniform_price: bool,        // Everyone pays the clearing price, the difference is rebated
+    },
+    BondingCurve {                  // Price follows `shape` as tokens sell, without steps
+        token_amount: u64,          // Tokens on sale, capped by `hardcap_amount`
+        base_price: u64,            // Price per whole token before any sale, `PRICE_DECIMALS` fixed point
+        shape: CurveShape,
+    },
+    BatchAuction {                  // Buyers bid at the level prices, everyone pays one clearing price
+        token_amount: u64,          // Tokens on sale, capped by `hardcap_amount`
+    },
+}
+
+impl Presale {
+    // Tokens the sale can hand out, the deposit must cover them
+    pub fn tokens_for_sale(&self) -> Result<u64> {
+        match self.sale_mode {
+            SaleMode::Tiered => self.levels
+                .iter()
+                .try_fold(0_u64, |total, level| total.checked_add(level.token_amount))
+                .ok_or(PresaleError::MathOverflow.into()),
+            SaleMode::Overflow { token_amount }
+            | SaleMode::DutchAuction { token_amount, .. }
+            | SaleMode::BondingCurve { token_amount, .. }
+            | SaleMode::BatchAuction { token_amount } => Ok(token_amount),
+        }
+    }
+
+    // Buys with `payment` base units of a `quote_decimals` mint at the sale's current prices,
+    // see `fill_levels`
+    pub fn fill_payment(&mut self, payment: u64, quote_decimals: u8, max_level: Option<u8>, now: u64) -> Result<(u64, u64, Vec<LevelFill>)> {
+        match self.sale_mode {
+            SaleMode::Tiered => self.fill_levels(payment, quote_decimals, max_level),
+            SaleMode::DutchAuction { .. } => {
+                let price = self.auction_price(now)?;
+                let tokens = self.tokens_for_quote(payment, price, quote_decimals)?
+                    .min(self.hardcap_amount.saturating_sub(self.sold_token_amount));
+                if tokens == 0 {
+                    return Ok((0, payment, Vec::new()));
+                }
+                let cost = self.quote_for_tokens(tokens, price, quote_decimals)?;
+                self.sell_tokens(tokens)?;
+                Ok((tokens, payment - cost, vec![LevelFill { level: 0, tokens, cost }]))
+            }
+            SaleMode::BondingCurve { .. } => {
+                let available = self.hardcap_amount.saturating_sub(self.sold_token_amount);
+                let tokens = self.curve_tokens_for_quote(payment, available, quote_decimals)?;
+                if tokens == 0 {
+                    return Ok((0, payment, Vec::new()));
+                }
+                let cost = self.curve_cost(self.sold_token_amount, tokens, quote_decimals)?;
+                self.sell_tokens(tokens)?;
+                Ok((tokens, payment - cost, vec![LevelFill { level: 0, tokens, cost }]))
+            }
+            SaleMode::Overflow { .. } | SaleMode::BatchAuction { .. } => Err(PresaleError::WrongSaleMode.into()),
+        }
+    }
+
+    // Buys exactly `token_amount` tokens at the sale's current prices, see `fill_levels_exact_out`
+    pub fn fill_exact_out(&mut self, token_amount: u64, quote_decimals: u8, max_level: Option<u8>, now: u64) -> Result<(u64, Vec<LevelFill>)> {
+        match self.sale_mode {
+            SaleMode::Tiered => self.fill_levels_exact_out(token_amount, quote_decimals, max_level),
+            SaleMode::DutchAuction { .. } => {
+                let price = self.auction_price(now)?;
+                let cost = self.quote_for_tokens(token_amount, price, quote_decimals)?;
+                self.sell_tokens(token_amount)?;
+                Ok((cost, vec![LevelFill { level: 0, tokens: token_amount, cost }]))
+            }
+            SaleMode::BondingCurve { .. } => {
+                let cost = self.curve_cost(self.sold_token_amount, token_amount, quote_decimals)?;
+                self.sell_tokens(token_amount)?;
+                Ok((cost, vec![LevelFill { level: 0, tokens: token_amount, cost }]))
+            }
+            SaleMode::Overflow { .. } | SaleMode::BatchAuction { .. } => Err(PresaleError::WrongSaleMode.into()),
+        }
+    }
+
+    // Dutch auction price at `now`: `start_price` until `start_time`, then falling linearly (or
+    // in `step_duration` steps) to `floor_price` at `end_time`. Rounded up between steps.
+    pub fn auction_price(&self, now: u64) -> Result<u64> {
+        let SaleMode::DutchAuction { start_price, floor_price, step_duration, .. } = self.sale_mode else {
+            return Err(PresaleError::WrongSaleMode.into());
+        };
+        let duration = self.end_time.saturating_sub(self.start_time);
+        let mut elapsed = now.clamp(self.start_time, self.end_time) - self.start_time;
+        if step_duration > 0 && elapsed < duration {
+            elapsed -= elapsed % step_duration;
+        }
+        if duration == 0 {
+            return Ok(floor_price);
+        }
+
+        let decay = ((start_price - floor_price) as u128)
+            .checked_mul(elapsed as u128)
+            .map(|x| x / duration as u128)
+            .ok_or(PresaleError::CalculationOverflow)?;
+        Ok(start_price - decay as u64)
+    }
+
+    // Whether purchases are only provisionally charged until the buyer's rebate is settled
+    pub fn has_rebates(&self) -> bool {
+        matches!(self.sale_mode, SaleMode::DutchAuction { uniform_price: true, .. })
+    }
+
+    // Part of `amount_paid` for `tokens` returned once a uniform-price auction stops, leaving the
+    // buyer charged the clearing price. It is the lowest the auction reached, so it never costs
+    // more than was paid
+    pub fn auction_rebate(&self, tokens: u64, amount_paid: u64, quote_decimals: u8) -> Result<u64> {
+        let amount_owed = self
+            .quote_for_tokens(tokens, self.clearing_price, quote_decimals)?
+            .min(amount_paid);
+        Ok(amount_paid - amount_owed)
+    }
+
+    // Overflow sales take commitments in the primary quote mint only and price nothing until the
+    // end: `hardcap_amount` quote buys every token, anything committed above it is refunded.
+    pub fn committed_amount(&self) -> u64 {
+        self.quote_mints[0].raised_amount
+    }
+
+    // Tokens allotted to and quote kept from `commitment` once the sale is over. Tokens are
+    // rounded down and the quote kept is rounded up, like level purchases.
+    pub fn overflow_allocation(&self, commitment: u64) -> Result<(u64, u64)> {
+        let SaleMode::Overflow { token_amount } = self.sale_mode else {
+            return Err(PresaleError::WrongSaleMode.into());
+        };
+        let committed = self.committed_amount() as u128;
+        let hardcap = self.hardcap_amount as u128;
+
+        let tokens = (commitment as u128)
+            .checked_mul(token_amount as u128)
+            .map(|x| x / committed.max(hardcap))
+            .and_then(|x| u64::try_from(x).ok())
+            .ok_or(PresaleError::CalculationOverflow)?;
+        let spent = if committed <= hardcap {
+            commitment
+        } else {
+            (commitment as u128)
+                .checked_mul(hardcap)
+                .map(|x| x.div_ceil(committed))
+                .and_then(|x| u64::try_from(x).ok())
+                .ok_or(PresaleError::CalculationOverflow)?
+        };
+
+        Ok((tokens, spent))
+    }
+
+    // Tokens owed to every committer together, rounded up so the vault always covers them
+    pub fn overflow_tokens_sold(&self) -> Result<u64> {
+        let SaleMode::Overflow { token_amount } = self.sale_mode else {
+            return Err(PresaleError::WrongSaleMode.into());
+        };
+        let hardcap = self.hardcap_amount as u128;
+
+        (self.committed_amount() as u128)
+            .min(hardcap)
+            .checked_mul(token_amount as u128)
+            .map(|x| x.div_ceil(hardcap))
+            .and_then(|x| u64::try_from(x).ok())
+            .ok_or(PresaleError::CalculationOverflow.into())
+    }
+
+    // Quote in the `index` vault that still belongs to buyers and cannot be withdrawn. Uniform
+    // price auctions keep unsettled payments whole, they are released by `claim_rebate`.
+    pub fn reserved_quote(&self, index: usize) -> Result<u64> {
+        let reserved = self.quote_mints[index].reserved_amount;
+        if reserved == 0 || index != 0 || !matches!(self.sale_mode, SaleMode::Overflow { .. }) {
+            return Ok(reserved);
+        }
+
+        // Unsettled commitments keep only their refund reserved, the part spent on tokens
+        // (at least `reserved * hardcap / committed` across them) belongs to the presale
+        let committed = self.committed_amount() as u128;
+        let spent = (reserved as u128)
+            .checked_mul(committed.min(self.hardcap_amount as u128))
+            .and_then(|x| x.checked_div(committed))
+            .and_then(|x| u64::try_from(x).ok())
+            .ok_or(PresaleError::CalculationOverflow)?;
+        Ok(reserved - spent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{testing::{presale, DECIMALS, END_TIME, UNIT}, PresaleStatus};
+
+    // 200 tokens falling from 0.1 to 0.02 over the sale
+    fn dutch_auction(step_duration: u64) -> Presale {
+        let sale_mode = SaleMode::DutchAuction {
+            token_amount: 200 * UNIT,
+            start_price: 100_000,
+            floor_price: 20_000,
+            step_duration,
+            uniform_price: true,
+        };
+        presale(sale_mode, Vec::new(), 0, 200 * UNIT)
+    }
+
+    #[test]
+    fn auction_price_decays_linearly() {
+        let presale = dutch_auction(0);
+
+        assert_eq!(presale.auction_price(0).unwrap(), 100_000);
+        assert_eq!(presale.auction_price(1).unwrap(), 99_920);
+        assert_eq!(presale.auction_price(250).unwrap(), 80_000);
+        assert_eq!(presale.auction_price(500).unwrap(), 60_000);
+        assert_eq!(presale.auction_price(END_TIME).unwrap(), 20_000);
+    }
+
+    #[test]
+    fn auction_price_decays_in_steps() {
+        let presale = dutch_auction(100);
+
+        assert_eq!(presale.auction_price(99).unwrap(), 100_000);
+        assert_eq!(presale.auction_price(100).unwrap(), 92_000);
+        assert_eq!(presale.auction_price(199).unwrap(), 92_000);
+        assert_eq!(presale.auction_price(200).unwrap(), 84_000);
+        assert_eq!(presale.auction_price(999).unwrap(), 28_000);
+        // The last step lands on the floor at `end_time`
+        assert_eq!(presale.auction_price(END_TIME).unwrap(), 20_000);
+    }
+
+    #[test]
+    fn auction_price_is_clamped_to_the_sale_window() {
+        let mut presale = dutch_auction(0);
+        presale.start_time = 100;
+
+        assert_eq!(presale.auction_price(0).unwrap(), 100_000);
+        assert_eq!(presale.auction_price(END_TIME + 1).unwrap(), 20_000);
+        assert_eq!(presale.auction_price(u64::MAX).unwrap(), 20_000);
+    }
+
+    #[test]
+    fn rebates_charge_every_buyer_the_clearing_price() {
+        let mut presale = dutch_auction(0);
+
+        // 10 buys 100 tokens at 0.1, 6 buys the last 100 at 0.06 and hits the hardcap
+        let (early_tokens, _, _) = presale.fill_payment(10 * UNIT, DECIMALS, None, 0).unwrap();
+        let (late_tokens, _, _) = presale.fill_payment(6 * UNIT, DECIMALS, None, 500).unwrap();
+        assert_eq!((early_tokens, late_tokens), (100 * UNIT, 100 * UNIT));
+
+        // The auction stops where the hardcap was hit
+        assert_eq!(presale.sync_status(500), PresaleStatus::Succeeded);
+        assert_eq!(presale.clearing_price, 60_000);
+
+        assert_eq!(presale.auction_rebate(early_tokens, 10 * UNIT, DECIMALS).unwrap(), 4 * UNIT);
+        assert_eq!(presale.auction_rebate(late_tokens, 6 * UNIT, DECIMALS).unwrap(), 0);
+    }
+
+    #[test]
+    fn auction_ending_at_end_time_clears_at_the_floor() {
+        let mut presale = dutch_auction(0);
+        let (tokens, _, _) = presale.fill_payment(10 * UNIT, DECIMALS, None, 0).unwrap();
+
+        assert_eq!(presale.sync_status(END_TIME), PresaleStatus::Succeeded);
+        assert_eq!(presale.clearing_price, 20_000);
+        // 100 tokens at 0.02 cost 2 of the 10 paid
+        assert_eq!(presale.auction_rebate(tokens, 10 * UNIT, DECIMALS).unwrap(), 8 * UNIT);
+    }
+
+    // 1,000 tokens for the first 100 committed
+    fn overflow(softcap_amount: u64) -> Presale {
+        presale(SaleMode::Overflow { token_amount: 1_000 * UNIT }, Vec::new(), softcap_amount, 100 * UNIT)
+    }
+
+    // What `commit_quote` records for a commitment
+    fn commit(presale: &mut Presale, amount: u64) {
+        presale.quote_mints[0].raised_amount += amount;
+        presale.quote_mints[0].reserved_amount += amount;
+        presale.sold_token_amount = presale.overflow_tokens_sold().unwrap();
+    }
+
+    #[test]
+    fn undersubscribed_overflow_sale_spends_every_commitment() {
+        let mut presale = overflow(50 * UNIT);
+        commit(&mut presale, 40 * UNIT);
+        assert_eq!(presale.status_at(END_TIME), PresaleStatus::Failed);
+
+        commit(&mut presale, 20 * UNIT);
+        assert_eq!(presale.status_at(END_TIME), PresaleStatus::Succeeded);
+        assert_eq!(presale.sold_token_amount, 600 * UNIT);
+        assert_eq!(presale.overflow_allocation(40 * UNIT).unwrap(), (400 * UNIT, 40 * UNIT));
+        assert_eq!(presale.overflow_allocation(20 * UNIT).unwrap(), (200 * UNIT, 20 * UNIT));
+    }
+
+    #[test]
+    fn oversubscribed_overflow_sale_allocates_pro_rata() {
+        let mut presale = overflow(0);
+        for amount in [50 * UNIT, 100 * UNIT, 150 * UNIT] {
+            commit(&mut presale, amount);
+        }
+        assert_eq!(presale.sold_token_amount, 1_000 * UNIT);
+
+        // A third of the 300 committed is spent, tokens round down and the quote kept rounds up
+        assert_eq!(presale.overflow_allocation(50 * UNIT).unwrap(), (166_666_666, 16_666_667));
+        assert_eq!(presale.overflow_allocation(100 * UNIT).unwrap(), (333_333_333, 33_333_334));
+        assert_eq!(presale.overflow_allocation(150 * UNIT).unwrap(), (500 * UNIT, 50 * UNIT));
+    }
+
+    #[test]
+    fn unsettled_commitments_keep_their_refunds_reserved() {
+        let mut presale = overflow(0);
+        let commitments = [50 * UNIT, 100 * UNIT, 150 * UNIT];
+        for amount in commitments {
+            commit(&mut presale, amount);
+        }
+        let mut vault = 300 * UNIT;
+        let mut withdrawn = 0;
+        // What `withdraw_usd` takes: everything in the vault but the reserved quote
+        let mut withdraw = |presale: &Presale, vault: &mut u64| {
+            let reserved = presale.reserved_quote(0).unwrap();
+            assert!(*vault >= reserved);
+            withdrawn += *vault - reserved;
+            *vault = reserved;
+        };
+
+        // Before anyone settles exactly the hardcap can go
+        withdraw(&presale, &mut vault);
+        assert_eq!(vault, 200 * UNIT);
+
+        // Each settlement refunds out of what stayed reserved, withdrawing in between never
+        // leaves the vault short of the refunds still owed
+        let mut tokens_owed = 0;
+        for amount in commitments {
+            let (tokens, spent) = presale.overflow_allocation(amount).unwrap();
+            presale.quote_mints[0].reserved_amount -= amount;
+            vault -= amount - spent;
+            tokens_owed += tokens;
+            withdraw(&presale, &mut vault);
+        }
+
+        // The presale ends up with every amount spent, rounding dust included
+        assert_eq!(withdrawn, 100 * UNIT + 1);
+        assert_eq!(presale.reserved_quote(0).unwrap(), 0);
+        assert!(tokens_owed <= presale.sold_token_amount);
+    }
+}
@@ -0,0 +1,55 @@
+use anchor_lang::prelude::*;
+
+use crate::state::{
+    Level, Presale, PresaleRoles, PresaleStatus, PurchaseLimits, QuoteMint, SaleMode, VestingSchedule,
+};
+
+// Whole tokens and quote amounts in base units, both mints use 6 decimals
+pub const DECIMALS: u8 = 6;
+pub const UNIT: u64 = 1_000_000;
+// Sales run from 0 to `END_TIME`
+pub const END_TIME: u64 = 1_000;
+
+pub fn level(token_amount: u64, price: u64, soft_cap: u64) -> Level {
+    Level { token_amount, price, soft_cap, tokens_sold: 0, raised: 0 }
+}
+
+// Live, funded presale with no limits, vesting or whitelist
+pub fn presale(sale_mode: SaleMode, levels: Vec<Level>, softcap_amount: u64, hardcap_amount: u64) -> Presale {
+    let mut presale = Presale {
+        seed: 0,
+        admin: Pubkey::default(),
+        authority: Pubkey::default(),
+        pending_authority: None,
+        roles: PresaleRoles::new(Pubkey::default()),
+        sale_mode,
+        status: PresaleStatus::Live,
+        pause_flags: 0,
+        paused_at: 0,
+        extend_on_resume: false,
+        current_level: 0,
+        levels,
+        sold_token_amount: 0,
+        token_mint_address: Pubkey::default(),
+        usd_mint: Pubkey::default(),
+        token_decimals: DECIMALS,
+        quote_mints: vec![QuoteMint::new(Pubkey::default(), DECIMALS)],
+        softcap_amount,
+        hardcap_amount,
+        deposit_token_amount: 0,
+        start_time: 0,
+        end_time: END_TIME,
+        clearing_price: 0,
+        vesting: VestingSchedule { tge_bps: VestingSchedule::MAX_BPS, cliff_duration: 0, vesting_duration: 0 },
+        sol_payment: None,
+        sol_raised_amount: 0,
+        limits: PurchaseLimits { min_purchase: 0, max_per_wallet: 0, max_tokens_per_wallet: 0 },
+        whitelist_root: None,
+        referral: None,
+        referral_token_amount: 0,
+        owed_token_amount: 0,
+        bump: 0,
+    };
+    presale.deposit_token_amount = presale.tokens_for_sale().unwrap();
+    presale
+}
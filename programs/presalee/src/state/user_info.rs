@@ -1,12 +1,18 @@
 use anchor_lang::prelude::*;
 
+use crate::{errors::PresaleError, state::MAX_QUOTE_MINTS};
+
 #[account]
 #[derive(InitSpace)]
 pub struct UserInfo{
     // buyer's address
     pub buyer: Pubkey,
-    // Buy quote amount
+    // Buy quote amount, in the primary quote mint's units
     pub buy_quote_amount: u64,
+    // Quote paid per currency, indexed like `Presale::quote_mints`
+    pub quote_amounts: [u64; MAX_QUOTE_MINTS],
+    // Lamports paid through SOL purchases
+    pub sol_amount: u64,
     // Buy token amount
     pub buy_token_amount: u64,
     // user has claimed token
@@ -15,10 +21,33 @@ pub struct UserInfo{
     pub has_claimed_refund: bool,
     // Buy time
     pub buy_time: u64,
-    // claim amount
+    // Total tokens claimed so far under the vesting schedule
     pub claim_amount: u64,
-    // claim time
+    // Time of the latest claim
     pub claim_time: u64,
     // bump
     pub bump: u8
+}
+
+impl UserInfo {
+    // Adds a purchase worth `amount_spent` quote to the buyer's totals, the caller
+    // records what was actually paid in `quote_amounts` or `sol_amount`
+    pub fn record_purchase(&mut self, buyer: Pubkey, amount_spent: u64, tokens_bought: u64, buy_time: u64) -> Result<()> {
+        if self.buy_quote_amount == 0 {
+            self.buyer = buyer;
+        }
+        self.buy_quote_amount = self.buy_quote_amount
+            .checked_add(amount_spent)
+            .ok_or(PresaleError::MathOverflow)?;
+        self.buy_token_amount = self.buy_token_amount
+            .checked_add(tokens_bought)
+            .ok_or(PresaleError::MathOverflow)?;
+        self.buy_time = buy_time;
+        Ok(())
+    }
+
+    // Whether any currency paid in has not been refunded yet
+    pub fn has_pending_refund(&self) -> bool {
+        self.sol_amount > 0 || self.quote_amounts.iter().any(|amount| *amount > 0)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,144 @@
+// Fixed-point helpers for curve pricing, all `Option` results are `None` on overflow
+
+// 1.0 in the fixed-point format used by `exp2_wad`
+pub const WAD: u128 = 1_000_000_000_000_000_000;
+// ln(2) * WAD
+pub const LN_2_WAD: u128 = 693_147_180_559_945_309;
+
+// `a * b / c` with a 256-bit intermediate, rounded down or up
+pub fn mul_div(a: u128, b: u128, c: u128, round_up: bool) -> Option<u128> {
+    if c == 0 {
+        return None;
+    }
+    if let Some(product) = a.checked_mul(b) {
+        return Some(if round_up { product.div_ceil(c) } else { product / c });
+    }
+
+    // Full product as two 128-bit halves, from 64-bit limbs
+    const MASK: u128 = u64::MAX as u128;
+    let (a_hi, a_lo) = (a >> 64, a & MASK);
+    let (b_hi, b_lo) = (b >> 64, b & MASK);
+    let lo_lo = a_lo * b_lo;
+    let hi_lo = a_hi * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let hi_hi = a_hi * b_hi;
+    let middle = (lo_lo >> 64) + (hi_lo & MASK) + (lo_hi & MASK);
+    let lo = (middle << 64) | (lo_lo & MASK);
+    let hi = hi_hi + (hi_lo >> 64) + (lo_hi >> 64) + (middle >> 64);
+
+    // The quotient must fit in 128 bits
+    if hi >= c {
+        return None;
+    }
+
+    // Schoolbook long division of (hi, lo) by c, one bit at a time
+    let mut remainder = hi;
+    let mut quotient = 0_u128;
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((lo >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= c {
+            remainder = remainder.wrapping_sub(c);
+            quotient |= 1;
+        }
+    }
+
+    if round_up && remainder > 0 {
+        quotient.checked_add(1)
+    } else {
+        Some(quotient)
+    }
+}
+
+// 2^x for a WAD fixed-point `x`, rounded down
+pub fn exp2_wad(x: u128) -> Option<u128> {
+    exp2_wad_rounded(x, false)
+}
+
+// 2^x for a WAD fixed-point `x`, rounded up
+pub fn exp2_wad_up(x: u128) -> Option<u128> {
+    exp2_wad_rounded(x, true)
+}
+
+fn exp2_wad_rounded(x: u128, round_up: bool) -> Option<u128> {
+    let whole = x / WAD;
+    if whole >= 128 {
+        return None;
+    }
+
+    // 2^fraction = e^(fraction * ln 2), the exponent is below 0.7 so the series converges fast.
+    // `LN_2_WAD` is ln 2 rounded down, one more bounds it from above
+    let ln_2 = if round_up { LN_2_WAD + 1 } else { LN_2_WAD };
+    let y = mul_div(x % WAD, ln_2, WAD, round_up)?;
+    let mut term = WAD;
+    let mut sum = WAD;
+    let mut k = 1;
+    while term > 0 {
+        term = term * y / WAD / k;
+        sum += term;
+        k += 1;
+    }
+    // Every term is truncated twice and the error it carries into the next one shrinks, so
+    // the series falls short of the exact sum by less than 3 units per term
+    if round_up {
+        sum += 3 * k;
+    }
+
+    sum.checked_mul(1_u128 << whole)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mul_div_rounds_down_or_up() {
+        assert_eq!(mul_div(7, 3, 2, false), Some(10));
+        assert_eq!(mul_div(7, 3, 2, true), Some(11));
+        assert_eq!(mul_div(8, 3, 2, true), Some(12));
+        assert_eq!(mul_div(1, 1, 0, false), None);
+    }
+
+    #[test]
+    fn mul_div_carries_products_past_128_bits() {
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX, false), Some(u128::MAX));
+        assert_eq!(mul_div(u128::MAX, 2, 4, false), Some((1 << 127) - 1));
+        assert_eq!(mul_div(u128::MAX, 2, 4, true), Some(1 << 127));
+        assert_eq!(mul_div(1 << 100, 1 << 100, 1 << 80, false), Some(1 << 120));
+
+        // Checked against arbitrary-precision arithmetic
+        let (a, b, c) = (10_u128.pow(30) + 12_345, 10_u128.pow(30) + 6_789, 10_u128.pow(22) + 7);
+        assert_eq!(mul_div(a, b, c, false), Some(99_999_999_999_999_999_999_930_001_913_400_000_000));
+        assert_eq!(mul_div(a, b, c, true), Some(99_999_999_999_999_999_999_930_001_913_400_000_001));
+    }
+
+    #[test]
+    fn mul_div_rejects_quotients_past_128_bits() {
+        assert_eq!(mul_div(u128::MAX, u128::MAX, 1, false), None);
+        assert_eq!(mul_div(u128::MAX, 2, 1, false), None);
+        assert_eq!(mul_div(u128::MAX, 1, 1, true), Some(u128::MAX));
+    }
+
+    #[test]
+    fn exp2_is_exact_at_whole_powers() {
+        for power in [0_u32, 1, 5, 60] {
+            let x = power as u128 * WAD;
+            assert_eq!(exp2_wad(x), Some(WAD << power));
+            let up = exp2_wad_up(x).unwrap();
+            assert!(up >= WAD << power && up - (WAD << power) < 1_000 << power);
+        }
+        assert_eq!(exp2_wad(128 * WAD), None);
+    }
+
+    #[test]
+    fn exp2_brackets_the_exact_value() {
+        // 2^0.5 * WAD = 1414213562373095048.80..., 2^0.3 * WAD = 1231144413344916284.49...
+        for (x, exact_floor) in [(WAD / 2, 1_414_213_562_373_095_048), (3 * WAD / 10, 1_231_144_413_344_916_284)] {
+            let down = exp2_wad(x).unwrap();
+            let up = exp2_wad_up(x).unwrap();
+            assert!(down <= exact_floor && exact_floor - down < 100, "{down} below {exact_floor}");
+            assert!(up > exact_floor && up - exact_floor < 200, "{up} above {exact_floor}");
+        }
+    }
+}
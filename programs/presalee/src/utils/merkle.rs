@@ -0,0 +1,21 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::keccak::hashv;
+
+// Leaf committed to by the whitelist tree: keccak(buyer || max_allocation as little endian)
+pub fn whitelist_leaf(buyer: &Pubkey, max_allocation: u64) -> [u8; 32] {
+    hashv(&[buyer.as_ref(), &max_allocation.to_le_bytes()]).to_bytes()
+}
+
+// Verifies a proof built with sorted pair hashing (each parent is keccak of its two
+// children in ascending order), so the proof does not need to carry left/right flags
+pub fn verify_proof(proof: &[[u8; 32]], root: &[u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof.iter().fold(leaf, |node, sibling| {
+        if node <= *sibling {
+            hashv(&[&node, sibling]).to_bytes()
+        } else {
+            hashv(&[sibling, &node]).to_bytes()
+        }
+    });
+
+    computed == *root
+}
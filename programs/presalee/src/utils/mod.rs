@@ -0,0 +1,9 @@
+pub mod math;
+pub mod merkle;
+pub mod oracle;
+pub mod token;
+
+pub use math::*;
+pub use merkle::*;
+pub use oracle::*;
+pub use token::*;
@@ -0,0 +1,105 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::PresaleError;
+
+// Pyth-style price account layout (legacy v2 price account), only the fields we read
+pub const PRICE_ACCOUNT_MAGIC: u32 = 0xa1b2c3d4;
+pub const PRICE_STATUS_TRADING: u32 = 1;
+const MAGIC_OFFSET: usize = 0;
+const EXPO_OFFSET: usize = 20;
+const TIMESTAMP_OFFSET: usize = 96;
+const AGG_PRICE_OFFSET: usize = 208;
+const AGG_CONF_OFFSET: usize = 216;
+const AGG_STATUS_OFFSET: usize = 224;
+const PRICE_ACCOUNT_MIN_LEN: usize = 240;
+
+// Decimals of a lamport amount
+const SOL_DECIMALS: i32 = 9;
+
+pub struct OraclePrice {
+    pub price: u64,         // Aggregate USD price of one SOL, scaled by 10^expo
+    pub conf: u64,          // Confidence interval, same scale as price
+    pub expo: i32,
+    pub publish_time: i64,
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> [u8; N] {
+    data[offset..offset + N].try_into().unwrap()
+}
+
+impl OraclePrice {
+    pub fn load(feed: &AccountInfo) -> Result<Self> {
+        let data = feed.try_borrow_data()?;
+        require!(data.len() >= PRICE_ACCOUNT_MIN_LEN, PresaleError::InvalidPriceFeed);
+        require!(
+            u32::from_le_bytes(read(&data, MAGIC_OFFSET)) == PRICE_ACCOUNT_MAGIC,
+            PresaleError::InvalidPriceFeed
+        );
+        require!(
+            u32::from_le_bytes(read(&data, AGG_STATUS_OFFSET)) == PRICE_STATUS_TRADING,
+            PresaleError::InvalidPriceFeed
+        );
+
+        let price = i64::from_le_bytes(read(&data, AGG_PRICE_OFFSET));
+        require!(price > 0, PresaleError::InvalidPriceFeed);
+
+        Ok(Self {
+            price: price as u64,
+            conf: u64::from_le_bytes(read(&data, AGG_CONF_OFFSET)),
+            expo: i32::from_le_bytes(read(&data, EXPO_OFFSET)),
+            publish_time: i64::from_le_bytes(read(&data, TIMESTAMP_OFFSET)),
+        })
+    }
+
+    // Rejects prices older than `max_age` seconds or with a confidence wider than `max_conf_bps` of the price
+    pub fn check(&self, now: i64, max_age: u64, max_conf_bps: u16) -> Result<()> {
+        let age = now.saturating_sub(self.publish_time);
+        require!(age >= 0 && age as u64 <= max_age, PresaleError::StalePrice);
+        require!(
+            (self.conf as u128) * 10_000 <= (self.price as u128) * (max_conf_bps as u128),
+            PresaleError::PriceConfidenceTooWide
+        );
+        Ok(())
+    }
+
+    // Power of ten converting lamports * price into quote units
+    fn scale_exponent(&self, quote_decimals: u8) -> i32 {
+        quote_decimals as i32 + self.expo - SOL_DECIMALS
+    }
+
+    // Quote value of `lamports`, rounded down
+    pub fn lamports_to_quote(&self, lamports: u64, quote_decimals: u8) -> Result<u64> {
+        let value = (lamports as u128)
+            .checked_mul(self.price as u128)
+            .ok_or(PresaleError::MathOverflow)?;
+        let exponent = self.scale_exponent(quote_decimals);
+        let scale = 10u128
+            .checked_pow(exponent.unsigned_abs())
+            .ok_or(PresaleError::MathOverflow)?;
+
+        let quote = if exponent >= 0 {
+            value.checked_mul(scale).ok_or(PresaleError::MathOverflow)?
+        } else {
+            value / scale
+        };
+
+        u64::try_from(quote).map_err(|_| PresaleError::MathOverflow.into())
+    }
+
+    // Lamports needed to pay `quote` units, rounded up
+    pub fn quote_to_lamports(&self, quote: u64, quote_decimals: u8) -> Result<u64> {
+        let exponent = self.scale_exponent(quote_decimals);
+        let scale = 10u128
+            .checked_pow(exponent.unsigned_abs())
+            .ok_or(PresaleError::MathOverflow)?;
+
+        let (numerator, denominator) = if exponent >= 0 {
+            (quote as u128, (self.price as u128).checked_mul(scale).ok_or(PresaleError::MathOverflow)?)
+        } else {
+            ((quote as u128).checked_mul(scale).ok_or(PresaleError::MathOverflow)?, self.price as u128)
+        };
+        let lamports = numerator.div_ceil(denominator);
+
+        u64::try_from(lamports).map_err(|_| PresaleError::MathOverflow.into())
+    }
+}
@@ -0,0 +1,27 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions},
+};
+
+use crate::errors::PresaleError;
+
+// Gross amount to send so that the recipient is credited `net_amount` after any
+// Token-2022 transfer fee. Legacy SPL mints and mints without the extension pay no fee.
+pub fn amount_with_transfer_fee(mint: &AccountInfo, net_amount: u64) -> Result<u64> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(net_amount);
+    }
+
+    let mint_data = mint.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    let Ok(transfer_fee_config) = mint_state.get_extension::<TransferFeeConfig>() else {
+        return Ok(net_amount);
+    };
+
+    let fee = transfer_fee_config
+        .calculate_inverse_epoch_fee(Clock::get()?.epoch, net_amount)
+        .ok_or(PresaleError::MathOverflow)?;
+
+    Ok(net_amount.checked_add(fee).ok_or(PresaleError::MathOverflow)?)
+}
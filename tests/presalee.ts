@@ -5,14 +5,23 @@ import {
   PublicKey, 
   Keypair, 
   SystemProgram,
+  ComputeBudgetProgram,
 } from "@solana/web3.js";
 import { 
   createMint,
   createAssociatedTokenAccount,
+  getOrCreateAssociatedTokenAccount,
   mintTo,
+  getAccount,
   getAssociatedTokenAddress,
+  getMintLen,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  ExtensionType,
   TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
 } from "@solana/spl-token";
+import { keccak_256 } from "@noble/hashes/sha3";
 import { expect } from "chai";
 
 import adminSecretArray from "./wallets/wallet2.json";
@@ -54,16 +63,20 @@ describe("presalee", () => {
   const soldTokenAmount = new anchor.BN(0);
   const startTime = new anchor.BN(Math.floor(Date.now() / 1000));
   const endTime = new anchor.BN(Math.floor(Date.now() / 1000) + 3600 * 24 * 7); // 1 week
+  // Full unlock at TGE, no cliff, no linear vesting
+  const vesting = { tgeBps: 10000, cliffDuration: new anchor.BN(0), vestingDuration: new anchor.BN(0) };
+  // 1 USD minimum per purchase, 5,000 USD per wallet, no token cap
+  const limits = { minPurchase: new anchor.BN(1 * 10**6), maxPerWallet: new anchor.BN(5000 * 10**6), maxTokensPerWallet: new anchor.BN(0) };
   
   // Define presale levels (example tier structure)
   const levels = [
-    { tokenAmount: new anchor.BN(5000000 * 10**6), price: new anchor.BN(1 * 10**4), softCap: new anchor.BN(0), tokensSold: new anchor.BN(0) },
-    { tokenAmount: new anchor.BN(10000000 * 10**6), price: new anchor.BN(2 * 10**4), softCap: new anchor.BN(0), tokensSold: new anchor.BN(0) },
-    { tokenAmount: new anchor.BN(25000000 * 10**6), price: new anchor.BN(3 * 10**4), softCap: new anchor.BN(0), tokensSold: new anchor.BN(0) },
-    { tokenAmount: new anchor.BN(66000000 * 10**6), price: new anchor.BN(4.5 * 10**4), softCap: new anchor.BN(0), tokensSold: new anchor.BN(0) },
-    { tokenAmount: new anchor.BN(62000000 * 10**6), price: new anchor.BN(5.5 * 10**4), softCap: new anchor.BN(0), tokensSold: new anchor.BN(0) },
-    { tokenAmount: new anchor.BN(22000000 * 10**6), price: new anchor.BN(6.5 * 10**4), softCap: new anchor.BN(0), tokensSold: new anchor.BN(0) },
-    { tokenAmount: new anchor.BN(5000000), price: new anchor.BN(7.5 * 10**4), softCap: new anchor.BN(0), tokensSold: new anchor.BN(0) },
+    { tokenAmount: new anchor.BN(5000000 * 10**6), price: new anchor.BN(1 * 10**4), softCap: new anchor.BN(0) },
+    { tokenAmount: new anchor.BN(10000000 * 10**6), price: new anchor.BN(2 * 10**4), softCap: new anchor.BN(0) },
+    { tokenAmount: new anchor.BN(25000000 * 10**6), price: new anchor.BN(3 * 10**4), softCap: new anchor.BN(0) },
+    { tokenAmount: new anchor.BN(66000000 * 10**6), price: new anchor.BN(4.5 * 10**4), softCap: new anchor.BN(0) },
+    { tokenAmount: new anchor.BN(62000000 * 10**6), price: new anchor.BN(5.5 * 10**4), softCap: new anchor.BN(0) },
+    { tokenAmount: new anchor.BN(22000000 * 10**6), price: new anchor.BN(6.5 * 10**4), softCap: new anchor.BN(0) },
+    { tokenAmount: new anchor.BN(4000000 * 10**6), price: new anchor.BN(7.5 * 10**4), softCap: new anchor.BN(0) },
   ];
 
   before(async () => {
@@ -285,7 +298,11 @@ describe("presalee", () => {
           levels,
           soldTokenAmount,
           startTime,
-          endTime
+          endTime,
+          false, // started by hand through start_presale
+          vesting,
+          limits,
+          { tiered: {} }
         )
         .accountsPartial({
           admin: authority.publicKey,
@@ -296,6 +313,7 @@ describe("presalee", () => {
           vaultUsd,
           systemProgram: anchor.web3.SystemProgram.programId,
           tokenProgram: TOKEN_PROGRAM_ID,
+          quoteTokenProgram: TOKEN_PROGRAM_ID,
           associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
         })
         .signers([authority])
@@ -308,12 +326,48 @@ describe("presalee", () => {
       expect(presaleData.tokenMintAddress.toString()).to.equal(tokenMint.toString());
       expect(presaleData.softcapAmount.toString()).to.equal(softcapAmount.toString());
       expect(presaleData.hardcapAmount.toString()).to.equal(hardcapAmount.toString());
+      expect(presaleData.vesting.tgeBps).to.equal(vesting.tgeBps);
     } catch (error) {
       console.error("Error initializing presale:", error);
       throw error;
     }
   });
 
+  it("Updates configuration before launch", async () => {
+    const newEndTime = endTime.add(new anchor.BN(3600));
+
+    try {
+      await program.methods
+        .updatePresale({
+          softcapAmount: null,
+          hardcapAmount: null,
+          startTime: null,
+          endTime: newEndTime,
+          levels: null,
+          appendLevels: null,
+          vesting: null,
+          limits: null,
+        })
+        .accountsPartial({
+          admin: authority.publicKey,
+          newTokenMint: null,
+          newUsdMint: null,
+          presale: presalePda,
+          vaultDog: null,
+          tokenProgram: null,
+        })
+        .signers([authority])
+        .rpc();
+
+      const presaleData = await program.account.presale.fetch(presalePda);
+      expect(presaleData.endTime.toString()).to.equal(newEndTime.toString());
+      expect(presaleData.status).to.deep.equal({ draft: {} });
+    } catch (error) {
+      console.error("Error updating presale:", error);
+      throw error;
+    }
+  });
+
   it("Deposits tokens to presale", async () => {
     try {
       vaultDog = await getAssociatedTokenAddress(tokenMint, presalePda, true);
@@ -361,7 +415,7 @@ describe("presalee", () => {
       
       // Verify presale status changed
       const presaleData = await program.account.presale.fetch(presalePda);
-      // Add status check based on your state structure
+      expect(presaleData.status).to.deep.equal({ live: {} });
       
     } catch (error) {
       console.error("Error starting presale:", error);
@@ -372,13 +426,7 @@ describe("presalee", () => {
   it("Initializes user account", async () => {
     try {
       const tx = await program.methods
-        .initUser(
-          new anchor.BN(0), // buy_quote_amount
-          new anchor.BN(0), // buy_token_amount  
-          new anchor.BN(0), // buy_time
-          new anchor.BN(0), // claim_amount
-          new anchor.BN(0)  // claim_time
-        )
+        .initUser()
         .accountsPartial({
           buyer: user.publicKey,
           presale: presalePda,
@@ -393,6 +441,26 @@ describe("presalee", () => {
       // Verify user account was created
       const userData = await program.account.userInfo.fetch(userAccount);
       expect(userData.buyer.toString()).to.equal(user.publicKey.toString());
+      expect(userData.buyQuoteAmount.toNumber()).to.equal(0);
+      expect(userData.buyTokenAmount.toNumber()).to.equal(0);
+
+      // The account cannot be created a second time to reset its totals
+      let reinitialized = true;
+      try {
+        await program.methods
+          .initUser()
+          .accountsPartial({
+            buyer: user.publicKey,
+            presale: presalePda,
+            userInfo: userAccount,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+      } catch (error) {
+        reinitialized = false;
+      }
+      expect(reinitialized).to.be.false;
       
     } catch (error) {
       console.error("Error initializing user:", error);
@@ -406,7 +474,15 @@ describe("presalee", () => {
     try {
       vaultUsd = await getAssociatedTokenAddress(usdMint, presalePda, true);
       const tx = await program.methods
-        .buyTokens(paymentAmount)
+        .buyTokens(
+          paymentAmount,
+          new anchor.BN(1), // min_tokens_out
+          null, // max_level, any level
+          new anchor.BN(Math.floor(Date.now() / 1000) + 60), // deadline
+          { fillOrKill: {} }, // fill_mode
+          new anchor.BN(0), // max_allocation, open sale
+          [] // no whitelist proof
+        )
         .accountsPartial({
           buyer: user.publicKey,
           tokenMintAddress: tokenMint,
@@ -415,6 +491,7 @@ describe("presalee", () => {
           buyerAta: userUsdAccount,
           vaultUsd,
           user: userAccount,
+          referral: null,
           systemProgram: anchor.web3.SystemProgram.programId,
           associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
           tokenProgram: TOKEN_PROGRAM_ID,
@@ -434,6 +511,315 @@ describe("presalee", () => {
     }
   });
 
+  it("Buys an exact amount of tokens", async () => {
+    const tokenAmount = new anchor.BN(100 * 10**6); // 100 DGX
+    const maxPayment = new anchor.BN(5 * 10**6); // 5 USD
+
+    try {
+      const userBefore = await program.account.userInfo.fetch(userAccount);
+      const tx = await program.methods
+        .buyExactTokens(
+          tokenAmount,
+          maxPayment,
+          null, // max_level, any level
+          new anchor.BN(Math.floor(Date.now() / 1000) + 60), // deadline
+          new anchor.BN(0), // max_allocation, open sale
+          [] // no whitelist proof
+        )
+        .accountsPartial({
+          buyer: user.publicKey,
+          tokenMintAddress: tokenMint,
+          usdMint,
+          presale: presalePda,
+          buyerAta: userUsdAccount,
+          vaultUsd,
+          user: userAccount,
+          referral: null,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+      console.log("Buy exact tokens transaction signature:", tx);
+
+      const userData = await program.account.userInfo.fetch(userAccount);
+      expect(userData.buyTokenAmount.sub(userBefore.buyTokenAmount).toString()).to.equal(tokenAmount.toString());
+      expect(userData.buyQuoteAmount.sub(userBefore.buyQuoteAmount).lte(maxPayment)).to.be.true;
+    } catch (error) {
+      console.error("Error buying exact tokens:", error);
+      throw error;
+    }
+  });
+
+  it("Buys tokens with SOL through the price feed", async () => {
+    // Mock SOL/USD feed from tests/fixtures, only present on the local test validator
+    const priceFeed = new PublicKey("7UVimffxr9ow1uXYxsr4LHAcV58mLzhmwaeKvJ1pjLiE");
+    if (!(await connection.getAccountInfo(priceFeed))) {
+      console.log("Mock price feed not loaded, skipping SOL purchase");
+      return;
+    }
+
+    const [solVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("sol_vault"), presalePda.toBuffer()],
+      program.programId
+    );
+
+    try {
+      await program.methods
+        .setSolPriceFeed({
+          priceFeed,
+          maxPriceAge: new anchor.BN(10 * 365 * 24 * 3600), // fixture timestamp is fixed
+          maxConfidenceBps: 100,
+        })
+        .accountsPartial({
+          admin: authority.publicKey,
+          presale: presalePda,
+          solVault,
+        })
+        .signers([authority])
+        .rpc();
+
+      const userBefore = await program.account.userInfo.fetch(userAccount);
+      const tx = await program.methods
+        .buyTokensWithSol(
+          new anchor.BN(0.01 * anchor.web3.LAMPORTS_PER_SOL),
+          new anchor.BN(1),
+          null,
+          new anchor.BN(Math.floor(Date.now() / 1000) + 60),
+          { partialFill: {} },
+          new anchor.BN(0),
+          []
+        )
+        .accountsPartial({
+          buyer: user.publicKey,
+          usdMint,
+          presale: presalePda,
+          priceFeed,
+          solVault,
+          user: userAccount,
+          wsolMint: null,
+          buyerWsol: null,
+          wsolTransit: null,
+          tokenProgram: null,
+          associatedTokenProgram: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      console.log("Buy tokens with SOL transaction signature:", tx);
+
+      // 0.01 SOL at 150 USD is worth 1.5 USD
+      const userData = await program.account.userInfo.fetch(userAccount);
+      expect(userData.buyQuoteAmount.sub(userBefore.buyQuoteAmount).toNumber()).to.equal(1.5 * 10**6);
+      expect(userData.solAmount.gt(new anchor.BN(0))).to.be.true;
+    } catch (error) {
+      console.error("Error buying tokens with SOL:", error);
+      throw error;
+    }
+  });
+
+  it("Credits a referrer with a quote commission", async () => {
+    const [referralPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("referral"), presalePda.toBuffer(), authority.publicKey.toBuffer()],
+      program.programId
+    );
+
+    try {
+      await program.methods
+        .setReferralConfig({
+          commissionBps: 500, // 5%
+          payout: { quote: {} },
+          maxCommission: new anchor.BN(0), // uncapped
+        })
+        .accountsPartial({
+          admin: authority.publicKey,
+          presale: presalePda,
+        })
+        .signers([authority])
+        .rpc();
+
+      await program.methods
+        .registerReferrer()
+        .accountsPartial({
+          referrer: authority.publicKey,
+          presale: presalePda,
+          referral: referralPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([authority])
+        .rpc();
+
+      const paymentAmount = new anchor.BN(2 * 10**6); // 2 USD
+      await program.methods
+        .buyTokens(
+          paymentAmount,
+          new anchor.BN(1),
+          null,
+          new anchor.BN(Math.floor(Date.now() / 1000) + 60),
+          { fillOrKill: {} },
+          new anchor.BN(0),
+          []
+        )
+        .accountsPartial({
+          buyer: user.publicKey,
+          tokenMintAddress: tokenMint,
+          usdMint,
+          presale: presalePda,
+          buyerAta: userUsdAccount,
+          vaultUsd,
+          user: userAccount,
+          referral: referralPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+      const referral = await program.account.referral.fetch(referralPda);
+      expect(referral.quoteCommissions[0].toString()).to.equal(paymentAmount.muln(500).divn(10_000).toString());
+
+      const presaleData = await program.account.presale.fetch(presalePda);
+      expect(presaleData.quoteMints[0].reservedAmount.gte(referral.quoteCommissions[0])).to.be.true;
+    } catch (error) {
+      console.error("Error crediting referral:", error);
+      throw error;
+    }
+  });
+
+  it("Pauses and resumes buys (authority only)", async () => {
+    const PAUSE_BUYS = 1;
+
+    try {
+      await program.methods
+        .pausePresale(PAUSE_BUYS, true) // extend end_time by the paused duration
+        .accountsPartial({
+          admin: authority.publicKey,
+          presale: presalePda,
+        })
+        .signers([authority])
+        .rpc();
+
+      let presaleData = await program.account.presale.fetch(presalePda);
+      expect(presaleData.status).to.deep.equal({ paused: {} });
+      expect(presaleData.pauseFlags).to.equal(PAUSE_BUYS);
+
+      await program.methods
+        .resumePresale(PAUSE_BUYS)
+        .accountsPartial({
+          admin: authority.publicKey,
+          presale: presalePda,
+        })
+        .signers([authority])
+        .rpc();
+
+      presaleData = await program.account.presale.fetch(presalePda);
+      expect(presaleData.status).to.deep.equal({ live: {} });
+      expect(presaleData.pauseFlags).to.equal(0);
+    } catch (error) {
+      console.error("Error pausing presale:", error);
+      throw error;
+    }
+  });
+
+  it("Lets a dedicated pauser halt buys", async () => {
+    const PAUSE_BUYS = 1;
+
+    try {
+      await program.methods
+        .setRole({ pauser: {} }, user.publicKey)
+        .accountsPartial({
+          admin: authority.publicKey,
+          presale: presalePda,
+        })
+        .signers([authority])
+        .rpc();
+
+      await program.methods
+        .pausePresale(PAUSE_BUYS, false)
+        .accountsPartial({
+          admin: user.publicKey,
+          presale: presalePda,
+        })
+        .signers([user])
+        .rpc();
+
+      // Only the operator can resume
+      await program.methods
+        .resumePresale(PAUSE_BUYS)
+        .accountsPartial({
+          admin: authority.publicKey,
+          presale: presalePda,
+        })
+        .signers([authority])
+        .rpc();
+
+      const presaleData = await program.account.presale.fetch(presalePda);
+      expect(presaleData.roles.pauser.toBase58()).to.equal(user.publicKey.toBase58());
+      expect(presaleData.roles.treasurer.toBase58()).to.equal(authority.publicKey.toBase58());
+      expect(presaleData.status).to.deep.equal({ live: {} });
+    } catch (error) {
+      console.error("Error using pauser role:", error);
+      throw error;
+    }
+  });
+
+  it("Transfers the admin role and hands it back", async () => {
+    try {
+      // authority -> user
+      await program.methods
+        .proposeAdmin(user.publicKey)
+        .accountsPartial({
+          admin: authority.publicKey,
+          presale: presalePda,
+        })
+        .signers([authority])
+        .rpc();
+
+      await program.methods
+        .acceptAdmin()
+        .accountsPartial({
+          newAdmin: user.publicKey,
+          presale: presalePda,
+        })
+        .signers([user])
+        .rpc();
+
+      let presaleData = await program.account.presale.fetch(presalePda);
+      expect(presaleData.authority.toBase58()).to.equal(user.publicKey.toBase58());
+      expect(presaleData.admin.toBase58()).to.equal(authority.publicKey.toBase58());
+      expect(presaleData.pendingAuthority).to.be.null;
+
+      // user -> authority, the PDA address never changes
+      await program.methods
+        .proposeAdmin(authority.publicKey)
+        .accountsPartial({
+          admin: user.publicKey,
+          presale: presalePda,
+        })
+        .signers([user])
+        .rpc();
+
+      await program.methods
+        .acceptAdmin()
+        .accountsPartial({
+          newAdmin: authority.publicKey,
+          presale: presalePda,
+        })
+        .signers([authority])
+        .rpc();
+
+      presaleData = await program.account.presale.fetch(presalePda);
+      expect(presaleData.authority.toBase58()).to.equal(authority.publicKey.toBase58());
+    } catch (error) {
+      console.error("Error transferring admin:", error);
+      throw error;
+    }
+  });
+
   it("Ends presale (authority only)", async () => {
     try {
       const tx = await program.methods
@@ -446,6 +832,10 @@ describe("presalee", () => {
         .rpc();
 
       console.log("End presale transaction signature:", tx);
+
+      // Purchases above cleared the softcap
+      const presaleData = await program.account.presale.fetch(presalePda);
+      expect(presaleData.status).to.deep.equal({ succeeded: {} });
       
     } catch (error) {
       console.error("Error ending presale:", error);
@@ -576,6 +966,37 @@ describe("presalee", () => {
 
   it("Close presale (authority only)", async () => {
     try {
+      const closePresale = () => program.methods
+        .closePresale()
+        .accountsPartial({
+          admin: authority.publicKey,
+          presale: presalePda,
+          systemProgram: anchor.web3.SystemProgram.programId
+        })
+        .signers([authority])
+        .rpc();
+
+      // The referral commission is still held for the referrer
+      await expectError(closePresale(), "QuoteStillReserved");
+
+      const [referralPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("referral"), presalePda.toBuffer(), authority.publicKey.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .claimReferral()
+        .accountsPartial({
+          referrer: authority.publicKey,
+          mint: usdMint,
+          presale: presalePda,
+          referral: referralPda,
+          referrerAta: authorityUsdAccount,
+          vault: vaultUsd,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc();
+
       const tx = await program.methods
         .closePresale()
         .accountsPartial({
@@ -594,4 +1015,1382 @@ describe("presalee", () => {
     }
   });
   
-});
\ No newline at end of file
+  // Scenario suites below run against fresh presales with their own mints and buyers, so they
+  // do not depend on the state the walkthrough above leaves behind
+
+  type Sale = {
+    presale: PublicKey;
+    tokenMint: PublicKey;
+    usdMint: PublicKey;
+    tokenProgram: PublicKey;
+    quoteTokenProgram: PublicKey;
+    vaultDog: PublicKey;
+    vaultUsd: PublicKey;
+    buyer: Keypair;
+    buyerUsd: PublicKey;
+  };
+
+  type SaleOptions = {
+    levels?: any[];
+    softcap?: anchor.BN;
+    hardcap?: anchor.BN;
+    deposit?: anchor.BN;
+    // Tokens sent to the vault, defaults to `deposit`, nothing is deposited when zero
+    funding?: anchor.BN;
+    startTime?: number;
+    endTime?: number;
+    autoStart?: boolean;
+    // Calls start_presale once funded
+    start?: boolean;
+    saleMode?: object;
+    vesting?: object;
+    limits?: object;
+    tokenMint?: PublicKey;
+    usdMint?: PublicKey;
+    tokenProgram?: PublicKey;
+    quoteTokenProgram?: PublicKey;
+    // Decimals of the mints created when `tokenMint` or `usdMint` is not given
+    tokenDecimals?: number;
+    quoteDecimals?: number;
+  };
+
+  let nextSeed = new anchor.BN(Date.now());
+
+  const level = (tokens: number, price: number, softCap = 0, decimals = 6) => ({
+    tokenAmount: new anchor.BN(tokens).mul(new anchor.BN(10 ** decimals)),
+    price: new anchor.BN(price),
+    softCap: new anchor.BN(softCap),
+  });
+  const openLimits = { minPurchase: new anchor.BN(0), maxPerWallet: new anchor.BN(0), maxTokensPerWallet: new anchor.BN(0) };
+  const now = () => Math.floor(Date.now() / 1000);
+
+  async function fundedKeypair(): Promise<Keypair> {
+    const keypair = Keypair.generate();
+    const signature = await connection.requestAirdrop(keypair.publicKey, 2 * anchor.web3.LAMPORTS_PER_SOL);
+    await connection.confirmTransaction(signature, "confirmed");
+    return keypair;
+  }
+
+  // Mints `amount` of `mint` to `owner`, creating their token account if needed
+  async function fund(mint: PublicKey, owner: PublicKey, amount: anchor.BN, tokenProgram = TOKEN_PROGRAM_ID): Promise<PublicKey> {
+    const account = await getOrCreateAssociatedTokenAccount(
+      connection, authority, mint, owner, true, "confirmed", undefined, tokenProgram
+    );
+    await mintTo(connection, authority, mint, account.address, authority, amount.toNumber(), [], undefined, tokenProgram);
+    return account.address;
+  }
+
+  async function createSale(options: SaleOptions = {}): Promise<Sale> {
+    const tokenProgram = options.tokenProgram ?? TOKEN_PROGRAM_ID;
+    const quoteTokenProgram = options.quoteTokenProgram ?? TOKEN_PROGRAM_ID;
+    const tokenMint = options.tokenMint
+      ?? await createMint(connection, authority, authority.publicKey, null, options.tokenDecimals ?? 6, undefined, undefined, tokenProgram);
+    const usdMint = options.usdMint
+      ?? await createMint(connection, authority, authority.publicKey, null, options.quoteDecimals ?? 6, undefined, undefined, quoteTokenProgram);
+
+    const levels = options.levels ?? [level(1_000_000, 10_000), level(1_000_000, 20_000)];
+    const levelTotal = levels.reduce((total, l) => total.add(l.tokenAmount), new anchor.BN(0));
+    const deposit = options.deposit ?? levelTotal;
+    const startTime = options.startTime ?? now();
+    const endTime = options.endTime ?? now() + 3600;
+
+    nextSeed = nextSeed.addn(1);
+    const [presale] = PublicKey.findProgramAddressSync(
+      [Buffer.from("dogx_presale"), authority.publicKey.toBuffer(), nextSeed.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const vaultDog = await getAssociatedTokenAddress(tokenMint, presale, true, tokenProgram);
+    const vaultUsd = await getAssociatedTokenAddress(usdMint, presale, true, quoteTokenProgram);
+
+    await program.methods
+      .initPresale(
+        nextSeed,
+        tokenMint,
+        usdMint,
+        options.softcap ?? new anchor.BN(0),
+        options.hardcap ?? levelTotal,
+        deposit,
+        levels,
+        new anchor.BN(0),
+        new anchor.BN(startTime),
+        new anchor.BN(endTime),
+        options.autoStart ?? false,
+        options.vesting ?? vesting,
+        options.limits ?? openLimits,
+        options.saleMode ?? { tiered: {} }
+      )
+      .accountsPartial({
+        admin: authority.publicKey,
+        tokenMintAddress: tokenMint,
+        usdMint,
+        presale,
+        vaultDog,
+        vaultUsd,
+        tokenProgram,
+        quoteTokenProgram,
+      })
+      .signers([authority])
+      .rpc({ commitment: "confirmed" });
+
+    const funding = options.funding ?? deposit;
+    if (!funding.isZero()) {
+      const adminAta = await fund(tokenMint, authority.publicKey, funding, tokenProgram);
+      await program.methods
+        .depositToken(funding)
+        .accountsPartial({
+          admin: authority.publicKey,
+          usdMint,
+          tokenMintAddress: tokenMint,
+          adminAta,
+          vaultDog,
+          presale,
+          tokenProgram,
+        })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    if (options.start) {
+      await program.methods
+        .startPresale()
+        .accountsPartial({ admin: authority.publicKey, presale })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    const buyer = await fundedKeypair();
+    const buyerUsd = await fund(usdMint, buyer.publicKey, new anchor.BN(1_000_000).mul(new anchor.BN(10 ** (options.quoteDecimals ?? 6))), quoteTokenProgram);
+
+    return { presale, tokenMint, usdMint, tokenProgram, quoteTokenProgram, vaultDog, vaultUsd, buyer, buyerUsd };
+  }
+
+  function userInfoOf(sale: Sale, buyer = sale.buyer.publicKey): PublicKey {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), sale.presale.toBuffer(), buyer.toBuffer()],
+      program.programId
+    )[0];
+  }
+
+  // A quote currency of a sale: its mint, the presale's vault for it and the buyer's account
+  type Quote = { mint: PublicKey; vault: PublicKey; buyerAta: PublicKey };
+
+  type BuyOptions = {
+    buyer?: Keypair;
+    buyerUsd?: PublicKey;
+    maxAllocation?: anchor.BN;
+    proof?: number[][];
+    quote?: Quote;
+    minTokensOut?: anchor.BN;
+    maxLevel?: number;
+    deadline?: number;
+    fillMode?: object;
+    referral?: PublicKey;
+  };
+
+  function buy(sale: Sale, payment: anchor.BN, options: BuyOptions = {}): Promise<string> {
+    const buyer = options.buyer ?? sale.buyer;
+    return program.methods
+      .buyTokens(
+        payment,
+        options.minTokensOut ?? new anchor.BN(0),
+        options.maxLevel ?? null,
+        new anchor.BN(options.deadline ?? now() + 60),
+        options.fillMode ?? { partialFill: {} },
+        options.maxAllocation ?? new anchor.BN(0),
+        options.proof ?? []
+      )
+      .accountsPartial({
+        buyer: buyer.publicKey,
+        tokenMintAddress: sale.tokenMint,
+        usdMint: options.quote?.mint ?? sale.usdMint,
+        presale: sale.presale,
+        buyerAta: options.quote?.buyerAta ?? options.buyerUsd ?? sale.buyerUsd,
+        vaultUsd: options.quote?.vault ?? sale.vaultUsd,
+        user: userInfoOf(sale, buyer.publicKey),
+        referral: options.referral ?? null,
+        tokenProgram: sale.quoteTokenProgram,
+      })
+      .signers([buyer])
+      .rpc({ commitment: "confirmed" });
+  }
+
+  function endSale(sale: Sale): Promise<string> {
+    return program.methods
+      .endPresale()
+      .accountsPartial({ admin: authority.publicKey, presale: sale.presale })
+      .signers([authority])
+      .rpc({ commitment: "confirmed" });
+  }
+
+  async function claim(sale: Sale, buyer = sale.buyer): Promise<string> {
+    const buyerAta = await getAssociatedTokenAddress(sale.tokenMint, buyer.publicKey, false, sale.tokenProgram);
+    return program.methods
+      .claimToken()
+      .accountsPartial({
+        buyer: buyer.publicKey,
+        usdMint: sale.usdMint,
+        tokenMintAddress: sale.tokenMint,
+        buyerAta,
+        vaultDog: sale.vaultDog,
+        presale: sale.presale,
+        user: userInfoOf(sale, buyer.publicKey),
+        tokenProgram: sale.tokenProgram,
+      })
+      .signers([buyer])
+      .rpc({ commitment: "confirmed" });
+  }
+
+  // Token-2022 mint charging `feeBps` on every transfer
+  async function createTransferFeeMint(feeBps: number): Promise<PublicKey> {
+    const mint = Keypair.generate();
+    const space = getMintLen([ExtensionType.TransferFeeConfig]);
+    const lamports = await connection.getMinimumBalanceForRentExemption(space);
+    const transaction = new anchor.web3.Transaction().add(
+      SystemProgram.createAccount({
+        fromPubkey: authority.publicKey,
+        newAccountPubkey: mint.publicKey,
+        space,
+        lamports,
+        programId: TOKEN_2022_PROGRAM_ID,
+      }),
+      createInitializeTransferFeeConfigInstruction(
+        mint.publicKey, authority.publicKey, authority.publicKey, feeBps, BigInt(10 ** 15), TOKEN_2022_PROGRAM_ID
+      ),
+      createInitializeMintInstruction(mint.publicKey, 6, authority.publicKey, null, TOKEN_2022_PROGRAM_ID)
+    );
+    await anchor.web3.sendAndConfirmTransaction(connection, transaction, [authority, mint], { commitment: "confirmed" });
+    return mint.publicKey;
+  }
+
+  // Fails unless `promise` rejects with the program error `code`
+  async function expectError(promise: Promise<unknown>, code: string) {
+    let failed = false;
+    try {
+      await promise;
+    } catch (error) {
+      failed = true;
+      expect(error.error?.errorCode?.code, error.toString()).to.equal(code);
+    }
+    expect(failed, `expected ${code}`).to.be.true;
+  }
+
+  // Merkle helpers matching utils/merkle.rs: keccak(buyer || max_allocation le) leaves and
+  // sorted-pair parents
+  function whitelistLeaf(buyer: PublicKey, maxAllocation: anchor.BN): Buffer {
+    return Buffer.from(keccak_256(Buffer.concat([buyer.toBuffer(), maxAllocation.toArrayLike(Buffer, "le", 8)])));
+  }
+
+  function hashPair(a: Buffer, b: Buffer): Buffer {
+    return Buffer.from(keccak_256(Buffer.compare(a, b) <= 0 ? Buffer.concat([a, b]) : Buffer.concat([b, a])));
+  }
+
+  // Events emitted by the transaction `signature`, decoded from its logs
+  async function eventsOf(signature: string): Promise<anchor.Event[]> {
+    const transaction = await connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(transaction.meta.logMessages));
+  }
+
+  async function eventOf(signature: string, name: string) {
+    const event = (await eventsOf(signature)).find((e) => e.name === name);
+    expect(event, `expected a ${name} event`).to.not.be.undefined;
+    return event.data;
+  }
+
+  async function withdrawTokens(sale: Sale): Promise<string> {
+    const adminAta = await getAssociatedTokenAddress(sale.tokenMint, authority.publicKey, false, sale.tokenProgram);
+    return program.methods
+      .withdrawToken()
+      .accountsPartial({
+        admin: authority.publicKey,
+        usdMint: sale.usdMint,
+        tokenMintAddress: sale.tokenMint,
+        adminAta,
+        vaultDog: sale.vaultDog,
+        presale: sale.presale,
+        tokenProgram: sale.tokenProgram,
+      })
+      .signers([authority])
+      .rpc({ commitment: "confirmed" });
+  }
+
+  function closeSale(sale: Sale): Promise<string> {
+    return program.methods
+      .closePresale()
+      .accountsPartial({ admin: authority.publicKey, presale: sale.presale })
+      .signers([authority])
+      .rpc({ commitment: "confirmed" });
+  }
+
+  // Resolves once the cluster clock, which buys are checked against, reaches `timestamp`
+  async function waitUntil(timestamp: number) {
+    for (;;) {
+      const blockTime = await connection.getBlockTime(await connection.getSlot("confirmed"));
+      if (blockTime !== null && blockTime >= timestamp) return;
+      await new Promise((resolve) => setTimeout(resolve, 500));
+    }
+  }
+
+  function refund(sale: Sale, buyer = sale.buyer, quote?: Quote): Promise<string> {
+    return program.methods
+      .refund()
+      .accountsPartial({
+        buyer: buyer.publicKey,
+        admin: authority.publicKey,
+        tokenMintAddress: sale.tokenMint,
+        usdMint: quote?.mint ?? sale.usdMint,
+        presale: sale.presale,
+        buyerAta: quote?.buyerAta ?? sale.buyerUsd,
+        vaultUsd: quote?.vault ?? sale.vaultUsd,
+        user: userInfoOf(sale, buyer.publicKey),
+        tokenProgram: sale.quoteTokenProgram,
+      })
+      .signers([buyer])
+      .rpc({ commitment: "confirmed" });
+  }
+
+  describe("multiple quote mints", () => {
+    const usd = (amount: number) => new anchor.BN(amount * 10 ** 6);
+
+    // Accepts a second stablecoin in `sale` and funds the buyer with 1,000 of it
+    async function addQuote(sale: Sale, decimals = 6): Promise<Quote> {
+      const mint = await createMint(connection, authority, authority.publicKey, null, decimals);
+      const vault = await getAssociatedTokenAddress(mint, sale.presale, true);
+      await program.methods
+        .addQuoteMint()
+        .accountsPartial({
+          admin: authority.publicKey,
+          usdMint: sale.usdMint,
+          quoteMint: mint,
+          presale: sale.presale,
+          vaultQuote: vault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+      const buyerAta = await fund(mint, sale.buyer.publicKey, new anchor.BN(1_000).mul(new anchor.BN(10 ** decimals)));
+      return { mint, vault, buyerAta };
+    }
+
+    const balanceOf = async (account: PublicKey) => (await getAccount(connection, account, "confirmed")).amount.toString();
+
+    it("Refunds a failed sale in each currency paid", async () => {
+      const sale = await createSale({ start: true, softcap: new anchor.BN(100_000 * 10 ** 6) });
+      const second = await addQuote(sale);
+      const primary = { mint: sale.usdMint, vault: sale.vaultUsd, buyerAta: sale.buyerUsd };
+
+      await buy(sale, usd(10));
+      await buy(sale, usd(20), { quote: second });
+
+      const userData = await program.account.userInfo.fetch(userInfoOf(sale));
+      expect(userData.quoteAmounts.slice(0, 2).map((amount) => amount.toString())).to.deep.equal([usd(10).toString(), usd(20).toString()]);
+      expect(userData.buyTokenAmount.toString()).to.equal(usd(3_000).toString());
+
+      await endSale(sale);
+      const primaryBefore = new anchor.BN(await balanceOf(primary.buyerAta));
+      const secondBefore = new anchor.BN(await balanceOf(second.buyerAta));
+
+      await refund(sale, sale.buyer, second);
+      expect(await balanceOf(second.buyerAta)).to.equal(secondBefore.add(usd(20)).toString());
+      expect(await balanceOf(second.vault)).to.equal("0");
+      // Only the second currency was paid back so far
+      await expectError(refund(sale, sale.buyer, second), "NothingToRefund");
+
+      await refund(sale, sale.buyer, primary);
+      expect(await balanceOf(primary.buyerAta)).to.equal(primaryBefore.add(usd(10)).toString());
+      expect(await balanceOf(primary.vault)).to.equal("0");
+    });
+
+    it("Withdraws what was raised in each currency", async () => {
+      const sale = await createSale({ start: true });
+      const second = await addQuote(sale);
+
+      await buy(sale, usd(10));
+      await buy(sale, usd(20), { quote: second });
+      await endSale(sale);
+
+      const presaleData = await program.account.presale.fetch(sale.presale);
+      expect(presaleData.quoteMints.map((quote) => quote.raisedAmount.toString())).to.deep.equal([usd(10).toString(), usd(20).toString()]);
+
+      for (const [mint, vault, raised] of [[sale.usdMint, sale.vaultUsd, usd(10)], [second.mint, second.vault, usd(20)]] as const) {
+        const adminAta = (await getOrCreateAssociatedTokenAccount(connection, authority, mint, authority.publicKey, false, "confirmed")).address;
+        const before = new anchor.BN(await balanceOf(adminAta));
+        await program.methods
+          .withdrawUsd()
+          .accountsPartial({
+            admin: authority.publicKey,
+            usdMint: mint,
+            tokenMintAddress: sale.tokenMint,
+            adminAta,
+            vaultUsd: vault,
+            presale: sale.presale,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([authority])
+          .rpc({ commitment: "confirmed" });
+
+        expect(await balanceOf(adminAta)).to.equal(before.add(raised).toString());
+        expect(await balanceOf(vault)).to.equal("0");
+      }
+    });
+
+    it("Prices a 9-decimal token the same in quote mints of any decimals", async () => {
+      const tokens = (amount: number) => new anchor.BN(amount).mul(new anchor.BN(10 ** 9));
+      const sale = await createSale({
+        start: true,
+        tokenDecimals: 9,
+        levels: [level(1_000_000, 10_000, 0, 9), level(1_000_000, 20_000, 0, 9)],
+        limits: { ...openLimits, maxPerWallet: usd(20) },
+      });
+      const second = await addQuote(sale, 9);
+      const secondUsd = (amount: number) => new anchor.BN(amount).mul(new anchor.BN(10 ** 9));
+
+      const primaryEvent = await eventOf(await buy(sale, usd(10)), "tokensPurchased");
+      expect(primaryEvent.tokensBought.toString()).to.equal(tokens(1_000).toString());
+      const secondEvent = await eventOf(await buy(sale, secondUsd(10), { quote: second }), "tokensPurchased");
+      expect(secondEvent.tokensBought.toString()).to.equal(tokens(1_000).toString());
+      expect(secondEvent.amountSpent.toString()).to.equal(secondUsd(10).toString());
+
+      // The wallet limit counts both purchases in the primary mint's units
+      const userData = await program.account.userInfo.fetch(userInfoOf(sale));
+      expect(userData.buyQuoteAmount.toString()).to.equal(usd(20).toString());
+      expect(userData.quoteAmounts.slice(0, 2).map((amount) => amount.toString())).to.deep.equal([usd(10).toString(), secondUsd(10).toString()]);
+      await expectError(buy(sale, new anchor.BN(1), { quote: second }), "WalletLimitExceeded");
+    });
+  });
+  describe("SOL payments", () => {
+    // Mock SOL/USD feed at 150 USD from tests/fixtures, only present on the local test validator
+    const priceFeed = new PublicKey("7UVimffxr9ow1uXYxsr4LHAcV58mLzhmwaeKvJ1pjLiE");
+
+    const solVaultOf = (sale: Sale) => PublicKey.findProgramAddressSync(
+      [Buffer.from("sol_vault"), sale.presale.toBuffer()],
+      program.programId
+    )[0];
+
+    function buyWithSol(sale: Sale, lamports: number, buyer: Keypair): Promise<string> {
+      return program.methods
+        .buyTokensWithSol(new anchor.BN(lamports), new anchor.BN(0), null, new anchor.BN(now() + 60), { partialFill: {} }, new anchor.BN(0), [])
+        .accountsPartial({
+          buyer: buyer.publicKey,
+          usdMint: sale.usdMint,
+          presale: sale.presale,
+          priceFeed,
+          solVault: solVaultOf(sale),
+          user: userInfoOf(sale, buyer.publicKey),
+          wsolMint: null,
+          buyerWsol: null,
+          wsolTransit: null,
+          tokenProgram: null,
+          associatedTokenProgram: null,
+        })
+        .signers([buyer])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    function claimSolRefund(sale: Sale, buyer: Keypair): Promise<string> {
+      return program.methods
+        .claimSolRefund()
+        .accountsPartial({
+          buyer: buyer.publicKey,
+          presale: sale.presale,
+          solVault: solVaultOf(sale),
+          user: userInfoOf(sale, buyer.publicKey),
+        })
+        .signers([buyer])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    it("Takes purchases below the rent-exempt minimum and refunds them one by one", async function () {
+      if (!(await connection.getAccountInfo(priceFeed))) {
+        this.skip();
+      }
+
+      const sale = await createSale({ start: true, softcap: new anchor.BN(100_000 * 10 ** 6) });
+      await program.methods
+        .setSolPriceFeed({ priceFeed, maxPriceAge: new anchor.BN(10 * 365 * 24 * 3600), maxConfidenceBps: 100 })
+        .accountsPartial({ admin: authority.publicKey, presale: sale.presale, solVault: solVaultOf(sale) })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+
+      const rentExempt = await connection.getMinimumBalanceForRentExemption(0);
+      expect(await connection.getBalance(solVaultOf(sale), "confirmed")).to.equal(rentExempt);
+
+      // 0.0001 SOL each, worth 0.015 USD, far below the vault's rent-exempt minimum
+      const lamports = 100_000;
+      const other = await fundedKeypair();
+      await buyWithSol(sale, lamports, sale.buyer);
+      await buyWithSol(sale, lamports, other);
+      await endSale(sale);
+
+      // The first refund leaves the other buyer's lamports, below the minimum on their own
+      await claimSolRefund(sale, sale.buyer);
+      expect(await connection.getBalance(solVaultOf(sale), "confirmed")).to.equal(rentExempt + lamports);
+      await claimSolRefund(sale, other);
+      expect(await connection.getBalance(solVaultOf(sale), "confirmed")).to.equal(rentExempt);
+    });
+  });
+  describe("merkle whitelist", () => {
+    const allocation = new anchor.BN(50 * 10 ** 6);
+    let sale: Sale;
+    let proof: number[][];
+
+    before(async () => {
+      sale = await createSale({ start: true });
+
+      // Two-leaf tree: the buyer and another allowlisted wallet
+      const buyerLeaf = whitelistLeaf(sale.buyer.publicKey, allocation);
+      const otherLeaf = whitelistLeaf(Keypair.generate().publicKey, new anchor.BN(10 * 10 ** 6));
+      const root = hashPair(buyerLeaf, otherLeaf);
+      proof = [Array.from(otherLeaf)];
+
+      await program.methods
+        .setWhitelist(Array.from(root))
+        .accountsPartial({ admin: authority.publicKey, presale: sale.presale })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+    });
+
+    it("Rejects a proof that does not lead to the root", async () => {
+      const wrongProof = [Array.from(Keypair.generate().publicKey.toBuffer())];
+      await expectError(buy(sale, new anchor.BN(10 ** 6), { maxAllocation: allocation, proof: wrongProof }), "NotWhitelisted");
+    });
+
+    it("Rejects an allocation other than the one in the leaf", async () => {
+      await expectError(buy(sale, new anchor.BN(10 ** 6), { maxAllocation: allocation.muln(2), proof }), "NotWhitelisted");
+    });
+
+    it("Accepts a valid proof", async () => {
+      await buy(sale, new anchor.BN(20 * 10 ** 6), { maxAllocation: allocation, proof });
+
+      const userData = await program.account.userInfo.fetch(userInfoOf(sale));
+      expect(userData.buyQuoteAmount.toString()).to.equal((20 * 10 ** 6).toString());
+    });
+
+    it("Rejects purchases above the leaf allocation", async () => {
+      // 20 already spent, 31 more would exceed the 50 allocation
+      await expectError(buy(sale, new anchor.BN(31 * 10 ** 6), { maxAllocation: allocation, proof }), "AllocationExceeded");
+      await buy(sale, new anchor.BN(30 * 10 ** 6), { maxAllocation: allocation, proof });
+    });
+  });
+  describe("purchase limits", () => {
+    const usd = (amount: number) => new anchor.BN(amount * 10 ** 6);
+
+    it("Enforces the minimum purchase and the wallet quote limit", async () => {
+      const sale = await createSale({
+        start: true,
+        limits: { minPurchase: usd(5), maxPerWallet: usd(20), maxTokensPerWallet: new anchor.BN(0) },
+      });
+
+      await expectError(buy(sale, usd(5).subn(1)), "BelowMinimumPurchase");
+      await buy(sale, usd(5));
+      // Brings the wallet to exactly 20
+      await buy(sale, usd(15));
+      await expectError(buy(sale, usd(5)), "WalletLimitExceeded");
+    });
+
+    it("Enforces the wallet token limit", async () => {
+      // 1,500 tokens is 15 USD at 0.01
+      const sale = await createSale({
+        start: true,
+        limits: { minPurchase: new anchor.BN(0), maxPerWallet: new anchor.BN(0), maxTokensPerWallet: usd(1500) },
+      });
+
+      await buy(sale, usd(15));
+      await expectError(buy(sale, new anchor.BN(10_000)), "WalletTokenLimitExceeded");
+
+      const userData = await program.account.userInfo.fetch(userInfoOf(sale));
+      expect(userData.buyTokenAmount.toString()).to.equal(usd(1500).toString());
+    });
+  });
+  describe("purchase guards", () => {
+    const usd = (amount: number) => new anchor.BN(amount * 10 ** 6);
+
+    it("Rejects a fill below min_tokens_out", async () => {
+      const sale = await createSale({ start: true });
+
+      // 10 buys 1,000 tokens at 0.01
+      await expectError(buy(sale, usd(10), { minTokensOut: usd(1_000).addn(1) }), "SlippageExceeded");
+      await buy(sale, usd(10), { minTokensOut: usd(1_000) });
+    });
+
+    it("Rejects a purchase past its deadline", async () => {
+      const sale = await createSale({ start: true });
+
+      await expectError(buy(sale, usd(10), { deadline: now() - 60 }), "DeadlineExceeded");
+    });
+
+    it("Rejects a fill-or-kill purchase that would cross max_level", async () => {
+      const sale = await createSale({ start: true });
+
+      // Level 0 holds 10,000 worth, the other 5 would come from level 1
+      await expectError(buy(sale, usd(10_005), { maxLevel: 0, fillMode: { fillOrKill: {} } }), "MaxLevelExceeded");
+      await buy(sale, usd(10_000), { maxLevel: 0, fillMode: { fillOrKill: {} } });
+
+      const presaleData = await program.account.presale.fetch(sale.presale);
+      expect(presaleData.levels[1].tokensSold.toString()).to.equal("0");
+    });
+  });
+  describe("fill modes", () => {
+    const usd = (amount: number) => new anchor.BN(amount * 10 ** 6);
+
+    function buyExact(sale: Sale, tokenAmount: anchor.BN, maxPayment: anchor.BN): Promise<string> {
+      return program.methods
+        .buyExactTokens(tokenAmount, maxPayment, null, new anchor.BN(now() + 60), new anchor.BN(0), [])
+        .accountsPartial({
+          buyer: sale.buyer.publicKey,
+          tokenMintAddress: sale.tokenMint,
+          usdMint: sale.usdMint,
+          presale: sale.presale,
+          buyerAta: sale.buyerUsd,
+          vaultUsd: sale.vaultUsd,
+          user: userInfoOf(sale),
+          referral: null,
+          tokenProgram: sale.quoteTokenProgram,
+        })
+        .signers([sale.buyer])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    it("Rejects a fill-or-kill payment that only partly fills", async () => {
+      // One token unit costs 1.5 quote units, 4 units buy two token units for 3
+      const sale = await createSale({ start: true, levels: [level(1_000, 1_500_000)] });
+
+      await expectError(buy(sale, new anchor.BN(4), { fillMode: { fillOrKill: {} } }), "ExactPaymentRequired");
+      await buy(sale, new anchor.BN(4), { fillMode: { partialFill: {} } });
+
+      const userData = await program.account.userInfo.fetch(userInfoOf(sale));
+      expect(userData.buyTokenAmount.toString()).to.equal("2");
+      expect(userData.buyQuoteAmount.toString()).to.equal("3");
+    });
+
+    it("Charges a partial fill only for what is available", async () => {
+      const sale = await createSale({ start: true });
+      const before = await getAccount(connection, sale.buyerUsd, "confirmed");
+
+      // Level 0 only has 10,000 worth of tokens
+      await buy(sale, usd(10_005), { maxLevel: 0, fillMode: { partialFill: {} } });
+
+      const after = await getAccount(connection, sale.buyerUsd, "confirmed");
+      expect((before.amount - after.amount).toString()).to.equal(usd(10_000).toString());
+      const userData = await program.account.userInfo.fetch(userInfoOf(sale));
+      expect(userData.buyTokenAmount.toString()).to.equal(usd(1_000_000).toString());
+    });
+
+    it("Buys exact tokens only up to what a nearly sold out sale has left", async () => {
+      const sale = await createSale({ start: true });
+      // 1,950,000 of the 2,000,000 tokens
+      await buy(sale, usd(29_000));
+
+      await expectError(buyExact(sale, usd(50_000).addn(1), usd(2_000)), "HardCapped");
+      await buyExact(sale, usd(50_000), usd(1_000));
+
+      const presaleData = await program.account.presale.fetch(sale.presale);
+      expect(presaleData.soldTokenAmount.toString()).to.equal(usd(2_000_000).toString());
+    });
+  });
+  describe("vesting", () => {
+    it("Releases only the TGE share before the cliff", async () => {
+      // Half at TGE, the rest unlocks linearly over a month after a one-year cliff
+      const sale = await createSale({
+        start: true,
+        vesting: { tgeBps: 5000, cliffDuration: new anchor.BN(365 * 24 * 3600), vestingDuration: new anchor.BN(30 * 24 * 3600) },
+      });
+      await buy(sale, new anchor.BN(10 * 10 ** 6));
+      await endSale(sale);
+
+      const event = await eventOf(await claim(sale), "tokensClaimed");
+      expect(event.amount.toString()).to.equal((500 * 10 ** 6).toString());
+
+      const buyerAta = await getAssociatedTokenAddress(sale.tokenMint, sale.buyer.publicKey);
+      const balance = await getAccount(connection, buyerAta, "confirmed");
+      expect(balance.amount.toString()).to.equal((500 * 10 ** 6).toString());
+
+      // The account stays open for the locked half, which cannot be claimed yet
+      const userData = await program.account.userInfo.fetch(userInfoOf(sale));
+      expect(userData.claimAmount.toString()).to.equal((500 * 10 ** 6).toString());
+      await expectError(claim(sale), "NothingToClaim");
+    });
+  });
+  describe("Token-2022 transfer fees", () => {
+    const FEE_BPS = 100; // 1%
+    // Gross amount to send so `net` arrives after the fee, rounded up like the program
+    const grossFor = (net: anchor.BN) => net.muln(10_000).addn(10_000 - FEE_BPS - 1).divn(10_000 - FEE_BPS);
+
+    it("Records the quote actually received from a fee-charging quote mint", async () => {
+      const usdMint = await createTransferFeeMint(FEE_BPS);
+      const sale = await createSale({ usdMint, quoteTokenProgram: TOKEN_2022_PROGRAM_ID, start: true });
+
+      const payment = new anchor.BN(10 * 10 ** 6);
+      const buyerBefore = await getAccount(connection, sale.buyerUsd, "confirmed", TOKEN_2022_PROGRAM_ID);
+      await buy(sale, payment);
+
+      // The buyer covers the fee, the vault is credited the full payment
+      const vault = await getAccount(connection, sale.vaultUsd, "confirmed", TOKEN_2022_PROGRAM_ID);
+      const buyerAfter = await getAccount(connection, sale.buyerUsd, "confirmed", TOKEN_2022_PROGRAM_ID);
+      expect(vault.amount.toString()).to.equal(payment.toString());
+      expect((buyerBefore.amount - buyerAfter.amount) > BigInt(payment.toString())).to.be.true;
+
+      const presaleData = await program.account.presale.fetch(sale.presale);
+      expect(presaleData.quoteMints[0].raisedAmount.toString()).to.equal(vault.amount.toString());
+    });
+
+    it("Records the tokens actually deposited from a fee-charging sale mint", async () => {
+      const tokenMint = await createTransferFeeMint(FEE_BPS);
+      const deposit = new anchor.BN(2_000_000).mul(new anchor.BN(10 ** 6));
+      const sale = await createSale({
+        tokenMint,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        deposit,
+        funding: grossFor(deposit),
+        start: true,
+      });
+
+      const vault = await getAccount(connection, sale.vaultDog, "confirmed", TOKEN_2022_PROGRAM_ID);
+      const presaleData = await program.account.presale.fetch(sale.presale);
+      expect(presaleData.depositTokenAmount.toString()).to.equal(vault.amount.toString());
+      expect(presaleData.depositTokenAmount.gte(deposit)).to.be.true;
+
+      // Bought tokens leave the vault in full, the buyer receives them less the fee
+      await buy(sale, new anchor.BN(10 * 10 ** 6));
+      await endSale(sale);
+      await claim(sale);
+
+      const bought = new anchor.BN(1000 * 10 ** 6);
+      const buyerAta = await getAssociatedTokenAddress(tokenMint, sale.buyer.publicKey, false, TOKEN_2022_PROGRAM_ID);
+      const received = await getAccount(connection, buyerAta, "confirmed", TOKEN_2022_PROGRAM_ID);
+      const vaultAfter = await getAccount(connection, sale.vaultDog, "confirmed", TOKEN_2022_PROGRAM_ID);
+      expect((vault.amount - vaultAfter.amount).toString()).to.equal(bought.toString());
+      expect(received.amount.toString()).to.equal(bought.sub(bought.muln(FEE_BPS).divn(10_000)).toString());
+    });
+  });
+  describe("events", () => {
+    it("Emits TokensPurchased with the per-level breakdown", async () => {
+      const sale = await createSale({ start: true });
+
+      // 10,000 buys out level 0 at 0.01, the other 5,000 buys 250,000 tokens at 0.02
+      const signature = await buy(sale, new anchor.BN(15_000 * 10 ** 6));
+      const event = await eventOf(signature, "tokensPurchased");
+
+      expect(event.presale.toBase58()).to.equal(sale.presale.toBase58());
+      expect(event.buyer.toBase58()).to.equal(sale.buyer.publicKey.toBase58());
+      expect(event.quoteMint.toBase58()).to.equal(sale.usdMint.toBase58());
+      expect(event.amountSpent.toString()).to.equal((15_000 * 10 ** 6).toString());
+      expect(event.tokensBought.toString()).to.equal((1_250_000 * 10 ** 6).toString());
+      expect(event.levels.map((fill) => [fill.level, fill.tokens.toString(), fill.cost.toString()])).to.deep.equal([
+        [0, (1_000_000 * 10 ** 6).toString(), (10_000 * 10 ** 6).toString()],
+        [1, (250_000 * 10 ** 6).toString(), (5_000 * 10 ** 6).toString()],
+      ]);
+    });
+
+    it("Emits TokensClaimed with the claimed total", async () => {
+      const sale = await createSale({ start: true });
+      await buy(sale, new anchor.BN(10 * 10 ** 6));
+      await endSale(sale);
+
+      const event = await eventOf(await claim(sale), "tokensClaimed");
+      expect(event.presale.toBase58()).to.equal(sale.presale.toBase58());
+      expect(event.buyer.toBase58()).to.equal(sale.buyer.publicKey.toBase58());
+      expect(event.amount.toString()).to.equal((1000 * 10 ** 6).toString());
+      expect(event.totalClaimed.toString()).to.equal((1000 * 10 ** 6).toString());
+    });
+
+    it("Emits RefundClaimed when a failed sale is refunded", async () => {
+      // Softcap out of reach, ending the sale fails it
+      const sale = await createSale({ start: true, softcap: new anchor.BN(100_000 * 10 ** 6) });
+      await buy(sale, new anchor.BN(10 * 10 ** 6));
+      await endSale(sale);
+
+      const event = await eventOf(await refund(sale), "refundClaimed");
+      expect(event.presale.toBase58()).to.equal(sale.presale.toBase58());
+      expect(event.buyer.toBase58()).to.equal(sale.buyer.publicKey.toBase58());
+      expect(event.quoteMint.toBase58()).to.equal(sale.usdMint.toBase58());
+      expect(event.amount.toString()).to.equal((10 * 10 ** 6).toString());
+    });
+  });
+  describe("closing", () => {
+    it("Only closes a finalized sale once every sold token is claimed", async () => {
+      const sale = await createSale({ start: true });
+      await buy(sale, new anchor.BN(10 * 10 ** 6));
+      await endSale(sale);
+
+      // Succeeded but the unsold tokens are still in the vault
+      await expectError(closeSale(sale), "PresaleNotFinalized");
+
+      await withdrawTokens(sale);
+      await expectError(closeSale(sale), "TokensStillOwed");
+
+      await claim(sale);
+      await closeSale(sale);
+      expect(await connection.getAccountInfo(sale.presale, "confirmed")).to.be.null;
+    });
+
+    it("Keeps a failed sale open", async () => {
+      const sale = await createSale({ start: true, softcap: new anchor.BN(100_000 * 10 ** 6) });
+      await buy(sale, new anchor.BN(10 * 10 ** 6));
+      await endSale(sale);
+
+      await expectError(closeSale(sale), "PresaleNotFinalized");
+    });
+  });
+  describe("scheduled start", () => {
+    let sale: Sale;
+    let startTime: number;
+
+    before(async () => {
+      startTime = now() + 8;
+      sale = await createSale({ startTime, autoStart: true });
+    });
+
+    it("Rejects purchases before start_time", async () => {
+      await expectError(buy(sale, new anchor.BN(10 * 10 ** 6)), "PresaleNotStarted");
+    });
+
+    it("Opens at start_time without an admin transaction", async () => {
+      await waitUntil(startTime);
+      await buy(sale, new anchor.BN(10 * 10 ** 6));
+
+      const userData = await program.account.userInfo.fetch(userInfoOf(sale));
+      expect(userData.buyTokenAmount.toString()).to.equal((1000 * 10 ** 6).toString());
+      const presaleData = await program.account.presale.fetch(sale.presale);
+      expect(presaleData.status).to.deep.equal({ live: {} });
+    });
+  });
+  describe("changing the sale token", () => {
+    const noChanges = {
+      softcapAmount: null,
+      hardcapAmount: null,
+      startTime: null,
+      endTime: null,
+      levels: null,
+      appendLevels: null,
+      vesting: null,
+      limits: null,
+    };
+
+    function changeTokenMint(sale: Sale, newTokenMint: PublicKey, vaultDog: PublicKey | null = sale.vaultDog): Promise<string> {
+      return program.methods
+        .updatePresale(noChanges)
+        .accountsPartial({
+          admin: authority.publicKey,
+          newTokenMint,
+          newUsdMint: null,
+          presale: sale.presale,
+          vaultDog,
+          tokenProgram: vaultDog ? sale.tokenProgram : null,
+        })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    it("Replaces the mint while nothing has been deposited", async () => {
+      const sale = await createSale({ funding: new anchor.BN(0) });
+      const newMint = await createMint(connection, authority, authority.publicKey, null, 9);
+
+      await changeTokenMint(sale, newMint);
+
+      const presaleData = await program.account.presale.fetch(sale.presale);
+      expect(presaleData.tokenMintAddress.toBase58()).to.equal(newMint.toBase58());
+      expect(presaleData.tokenDecimals).to.equal(9);
+    });
+
+    it("Requires the current vault to show it is empty", async () => {
+      const sale = await createSale({ funding: new anchor.BN(0) });
+      const newMint = await createMint(connection, authority, authority.publicKey, null, 6);
+
+      await expectError(changeTokenMint(sale, newMint, null), "MintChangeNotAllowed");
+    });
+
+    it("Keeps the mint once tokens are deposited", async () => {
+      const sale = await createSale();
+      const newMint = await createMint(connection, authority, authority.publicKey, null, 6);
+
+      await expectError(changeTokenMint(sale, newMint), "MintChangeNotAllowed");
+    });
+  });
+  describe("update permissions", () => {
+    const noChanges = {
+      softcapAmount: null,
+      hardcapAmount: null,
+      startTime: null,
+      endTime: null,
+      levels: null,
+      appendLevels: null,
+      vesting: null,
+      limits: null,
+    };
+
+    function updateSale(sale: Sale, changes: object, signer = authority): Promise<string> {
+      return program.methods
+        .updatePresale({ ...noChanges, ...changes })
+        .accountsPartial({
+          admin: signer.publicKey,
+          newTokenMint: null,
+          newUsdMint: null,
+          presale: sale.presale,
+          vaultDog: null,
+          tokenProgram: null,
+        })
+        .signers([signer])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    it("Leaves the operator level edits only", async () => {
+      const sale = await createSale();
+      const operator = await fundedKeypair();
+      await program.methods
+        .setRole({ operator: {} }, operator.publicKey)
+        .accountsPartial({ admin: authority.publicKey, presale: sale.presale })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+
+      const limits = { minPurchase: new anchor.BN(10 ** 6), maxPerWallet: new anchor.BN(0), maxTokensPerWallet: new anchor.BN(0) };
+      await expectError(updateSale(sale, { limits }, operator), "Unauthorized");
+
+      const levels = [level(1_000_000, 15_000), level(1_000_000, 20_000)];
+      await updateSale(sale, { levels }, operator);
+      // The authority keeps every other field after handing the operator role away
+      await updateSale(sale, { limits });
+
+      const presaleData = await program.account.presale.fetch(sale.presale);
+      expect(presaleData.levels[0].price.toString()).to.equal("15000");
+      expect(presaleData.limits.minPurchase.toString()).to.equal((10 ** 6).toString());
+    });
+
+    it("Rejects a soft cap change on a configured level while live", async () => {
+      const sale = await createSale({ start: true });
+
+      const levels = [level(1_000_000, 10_000), level(1_000_000, 20_000, 5 * 10 ** 6)];
+      await expectError(updateSale(sale, { levels }), "NotEditableWhileLive");
+
+      // Restating the levels as they are goes through
+      await updateSale(sale, { levels: [level(1_000_000, 10_000), level(1_000_000, 20_000)] });
+    });
+  });
+  describe("funding", () => {
+    const levelTotal = new anchor.BN(2_000_000).mul(new anchor.BN(10 ** 6));
+
+    it("Refuses to start a sale the vault does not cover", async () => {
+      const sale = await createSale({ funding: levelTotal.divn(2) });
+      const startPresale = () => program.methods
+        .startPresale()
+        .accountsPartial({ admin: authority.publicKey, presale: sale.presale })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+
+      await expectError(startPresale(), "PresaleNotFunded");
+
+      // Topping the vault up to the declared total lets it start
+      const adminAta = await fund(sale.tokenMint, authority.publicKey, levelTotal.divn(2));
+      await program.methods
+        .depositToken(levelTotal.divn(2))
+        .accountsPartial({
+          admin: authority.publicKey,
+          usdMint: sale.usdMint,
+          tokenMintAddress: sale.tokenMint,
+          adminAta,
+          vaultDog: sale.vaultDog,
+          presale: sale.presale,
+          tokenProgram: sale.tokenProgram,
+        })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+      await startPresale();
+
+      const presaleData = await program.account.presale.fetch(sale.presale);
+      expect(presaleData.status).to.deep.equal({ live: {} });
+    });
+
+    it("Keeps an unfunded scheduled sale closed past start_time", async () => {
+      const sale = await createSale({ autoStart: true, startTime: now() - 10, funding: new anchor.BN(0) });
+
+      await expectError(buy(sale, new anchor.BN(10 * 10 ** 6)), "PresaleNotFunded");
+    });
+  });
+  describe("long level lists", () => {
+    // 100 tokens per level, level i is priced i * 0.01
+    const levelsFrom = (first: number, last: number) =>
+      Array.from({ length: last - first + 1 }, (_, i) => level(100, (first + i) * 10_000));
+    const tokens = (amount: number) => new anchor.BN(amount).mul(new anchor.BN(10 ** 6));
+
+    function appendLevels(sale: Sale, appendLevels: any[], hardcapAmount: anchor.BN | null = null): Promise<string> {
+      return program.methods
+        .updatePresale({
+          softcapAmount: null,
+          hardcapAmount,
+          startTime: null,
+          endTime: null,
+          levels: null,
+          appendLevels,
+          vesting: null,
+          limits: null,
+        })
+        .accountsPartial({
+          admin: authority.publicKey,
+          newTokenMint: null,
+          newUsdMint: null,
+          presale: sale.presale,
+          vaultDog: null,
+          tokenProgram: null,
+        })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    it("Builds a 32-level sale over several updates and buys through every level", async () => {
+      const sale = await createSale({ levels: levelsFrom(1, 12) });
+      const sizeBefore = (await connection.getAccountInfo(sale.presale, "confirmed")).data.length;
+
+      // Each batch grows the account by the 40 bytes a stored level takes
+      await appendLevels(sale, levelsFrom(13, 22));
+      const sizeAfter = (await connection.getAccountInfo(sale.presale, "confirmed")).data.length;
+      expect(sizeAfter - sizeBefore).to.equal(10 * 40);
+      await appendLevels(sale, levelsFrom(23, 32), tokens(3_200));
+
+      // Top the vault up to the 3,200 tokens the levels now hold
+      const adminAta = await fund(sale.tokenMint, authority.publicKey, tokens(2_000));
+      await program.methods
+        .depositToken(tokens(2_000))
+        .accountsPartial({
+          admin: authority.publicKey,
+          usdMint: sale.usdMint,
+          tokenMintAddress: sale.tokenMint,
+          adminAta,
+          vaultDog: sale.vaultDog,
+          presale: sale.presale,
+          tokenProgram: sale.tokenProgram,
+        })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+      await program.methods
+        .startPresale()
+        .accountsPartial({ admin: authority.publicKey, presale: sale.presale })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+
+      // 1 + 2 + ... + 32 = 528 buys out every level
+      await program.methods
+        .buyTokens(new anchor.BN(528 * 10 ** 6), tokens(3_200), null, new anchor.BN(now() + 60), { fillOrKill: {} }, new anchor.BN(0), [])
+        .accountsPartial({
+          buyer: sale.buyer.publicKey,
+          tokenMintAddress: sale.tokenMint,
+          usdMint: sale.usdMint,
+          presale: sale.presale,
+          buyerAta: sale.buyerUsd,
+          vaultUsd: sale.vaultUsd,
+          user: userInfoOf(sale),
+          referral: null,
+          tokenProgram: sale.quoteTokenProgram,
+        })
+        .preInstructions([ComputeBudgetProgram.setComputeUnitLimit({ units: 1_000_000 })])
+        .signers([sale.buyer])
+        .rpc({ commitment: "confirmed" });
+
+      const presaleData = await program.account.presale.fetch(sale.presale);
+      expect(presaleData.levels.length).to.equal(32);
+      expect(presaleData.levels.every((l) => l.tokensSold.eq(tokens(100)))).to.be.true;
+      expect(presaleData.currentLevel).to.equal(32);
+      expect(presaleData.status).to.deep.equal({ succeeded: {} });
+    });
+
+    it("Appends levels to a live sale", async () => {
+      const sale = await createSale({ levels: levelsFrom(1, 2), funding: tokens(300), start: true });
+
+      await appendLevels(sale, levelsFrom(3, 3), tokens(300));
+      await buy(sale, new anchor.BN(6 * 10 ** 6));
+
+      const presaleData = await program.account.presale.fetch(sale.presale);
+      expect(presaleData.levels[2].tokensSold.toString()).to.equal(tokens(100).toString());
+    });
+  });
+  describe("live hardcap changes", () => {
+    const raiseHardcap = (sale: Sale, hardcapAmount: anchor.BN) => program.methods
+      .updatePresale({
+        softcapAmount: null,
+        hardcapAmount,
+        startTime: null,
+        endTime: null,
+        levels: null,
+        appendLevels: null,
+        vesting: null,
+        limits: null,
+      })
+      .accountsPartial({
+        admin: authority.publicKey,
+        newTokenMint: null,
+        newUsdMint: null,
+        presale: sale.presale,
+        vaultDog: null,
+        tokenProgram: null,
+      })
+      .signers([authority])
+      .rpc({ commitment: "confirmed" });
+
+    it("Rejects a hardcap change in a live overflow sale", async () => {
+      const tokenAmount = new anchor.BN(1_000_000 * 10 ** 6);
+      const sale = await createSale({
+        levels: [],
+        saleMode: { overflow: { tokenAmount } },
+        deposit: tokenAmount,
+        hardcap: new anchor.BN(10_000 * 10 ** 6),
+        start: true,
+      });
+
+      await expectError(raiseHardcap(sale, new anchor.BN(20_000 * 10 ** 6)), "NotEditableWhileLive");
+    });
+
+    it("Still lets a live tiered sale raise its hardcap", async () => {
+      const sale = await createSale({ hardcap: new anchor.BN(1_000_000 * 10 ** 6), start: true });
+
+      await raiseHardcap(sale, new anchor.BN(2_000_000 * 10 ** 6));
+      const presaleData = await program.account.presale.fetch(sale.presale);
+      expect(presaleData.hardcapAmount.toString()).to.equal((2_000_000 * 10 ** 6).toString());
+    });
+  });
+  describe("overflow sales", () => {
+    const usd = (amount: number) => new anchor.BN(amount * 10 ** 6);
+    const balanceOf = async (account: PublicKey) => (await getAccount(connection, account, "confirmed")).amount.toString();
+
+    // 1,000 tokens for the first 100 USD committed
+    const overflowSale = (softcap = new anchor.BN(0)) => createSale({
+      levels: [],
+      saleMode: { overflow: { tokenAmount: usd(1_000) } },
+      deposit: usd(1_000),
+      softcap,
+      hardcap: usd(100),
+      start: true,
+    });
+
+    // A second committer funded with 1,000 USD
+    async function committer(sale: Sale): Promise<[Keypair, PublicKey]> {
+      const buyer = await fundedKeypair();
+      return [buyer, await fund(sale.usdMint, buyer.publicKey, usd(1_000))];
+    }
+
+    function commit(sale: Sale, amount: anchor.BN, buyer = sale.buyer, buyerUsd = sale.buyerUsd, referral: PublicKey | null = null): Promise<string> {
+      return program.methods
+        .commitQuote(amount, new anchor.BN(now() + 60), new anchor.BN(0), [])
+        .accountsPartial({
+          buyer: buyer.publicKey,
+          tokenMintAddress: sale.tokenMint,
+          usdMint: sale.usdMint,
+          presale: sale.presale,
+          buyerAta: buyerUsd,
+          vaultUsd: sale.vaultUsd,
+          user: userInfoOf(sale, buyer.publicKey),
+          referral,
+          tokenProgram: sale.quoteTokenProgram,
+        })
+        .signers([buyer])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    function settle(sale: Sale, buyer = sale.buyer, buyerUsd = sale.buyerUsd): Promise<string> {
+      return program.methods
+        .settleCommitment()
+        .accountsPartial({
+          buyer: buyer.publicKey,
+          usdMint: sale.usdMint,
+          presale: sale.presale,
+          buyerAta: buyerUsd,
+          vaultUsd: sale.vaultUsd,
+          user: userInfoOf(sale, buyer.publicKey),
+          tokenProgram: sale.quoteTokenProgram,
+        })
+        .signers([buyer])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    async function withdrawUsd(sale: Sale): Promise<anchor.BN> {
+      const adminAta = (await getOrCreateAssociatedTokenAccount(connection, authority, sale.usdMint, authority.publicKey, false, "confirmed")).address;
+      const before = new anchor.BN(await balanceOf(adminAta));
+      await program.methods
+        .withdrawUsd()
+        .accountsPartial({
+          admin: authority.publicKey,
+          usdMint: sale.usdMint,
+          tokenMintAddress: sale.tokenMint,
+          adminAta,
+          vaultUsd: sale.vaultUsd,
+          presale: sale.presale,
+          tokenProgram: sale.quoteTokenProgram,
+        })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+      return new anchor.BN(await balanceOf(adminAta)).sub(before);
+    }
+
+    it("Allocates an oversubscribed sale pro rata and refunds the rest", async () => {
+      const sale = await overflowSale();
+      const [other, otherUsd] = await committer(sale);
+
+      // 300 committed for a 100 hardcap, a third of each commitment is spent
+      await commit(sale, usd(100));
+      await commit(sale, usd(200), other, otherUsd);
+      await endSale(sale);
+
+      // Unsettled commitments keep their refunds reserved, the hardcap can be withdrawn already
+      expect((await withdrawUsd(sale)).toString()).to.equal(usd(100).toString());
+      expect(await balanceOf(sale.vaultUsd)).to.equal(usd(200).toString());
+
+      // Tokens round down, the quote kept rounds up
+      const buyerBefore = new anchor.BN(await balanceOf(sale.buyerUsd));
+      const event = await eventOf(await settle(sale), "commitmentSettled");
+      expect(event.tokens.toString()).to.equal("333333333");
+      expect(event.amountSpent.toString()).to.equal("33333334");
+      expect(event.refund.toString()).to.equal("66666666");
+      expect(await balanceOf(sale.buyerUsd)).to.equal(buyerBefore.addn(66_666_666).toString());
+      await expectError(settle(sale), "NothingToSettle");
+
+      const otherEvent = await eventOf(await settle(sale, other, otherUsd), "commitmentSettled");
+      expect(otherEvent.tokens.toString()).to.equal("666666666");
+      expect(otherEvent.refund.toString()).to.equal("133333333");
+
+      // The rounding dust left over belongs to the presale
+      expect(await balanceOf(sale.vaultUsd)).to.equal("1");
+      expect((await withdrawUsd(sale)).toString()).to.equal("1");
+
+      const userData = await program.account.userInfo.fetch(userInfoOf(sale));
+      expect(userData.buyQuoteAmount.toString()).to.equal("33333334");
+      const claimed = await eventOf(await claim(sale), "tokensClaimed");
+      expect(claimed.amount.toString()).to.equal("333333333");
+    });
+
+    it("Refunds every commitment in full when the soft cap is missed", async () => {
+      const sale = await overflowSale(usd(500));
+      await commit(sale, usd(100));
+      await endSale(sale);
+
+      await expectError(settle(sale), "SoftCapNotReached");
+      const before = new anchor.BN(await balanceOf(sale.buyerUsd));
+      await refund(sale);
+      expect(await balanceOf(sale.buyerUsd)).to.equal(before.add(usd(100)).toString());
+      expect(await balanceOf(sale.vaultUsd)).to.equal("0");
+    });
+
+    it("Rejects a referrer on a commitment", async () => {
+      const sale = await overflowSale();
+      await program.methods
+        .setReferralConfig({ commissionBps: 500, payout: { quote: {} }, maxCommission: new anchor.BN(0) })
+        .accountsPartial({ admin: authority.publicKey, presale: sale.presale })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+      const [referral] = PublicKey.findProgramAddressSync(
+        [Buffer.from("referral"), sale.presale.toBuffer(), authority.publicKey.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .registerReferrer()
+        .accountsPartial({ referrer: authority.publicKey, presale: sale.presale, referral })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+
+      await expectError(commit(sale, usd(10), sale.buyer, sale.buyerUsd, referral), "ReferralNotSupported");
+      await commit(sale, usd(10));
+    });
+  });
+  describe("uniform price auctions", () => {
+    const usd = (amount: number) => new anchor.BN(amount * 10 ** 6);
+    const balanceOf = async (account: PublicKey) => (await getAccount(connection, account, "confirmed")).amount.toString();
+
+    // 1,000 tokens falling from 0.1 to 0.02 over an hour, everyone pays the clearing price
+    const auctionSale = () => createSale({
+      levels: [],
+      saleMode: { dutchAuction: { tokenAmount: usd(1_000), startPrice: new anchor.BN(100_000), floorPrice: new anchor.BN(20_000), stepDuration: new anchor.BN(0), uniformPrice: true } },
+      deposit: usd(1_000),
+      hardcap: usd(1_000),
+      start: true,
+    });
+
+    function claimRebate(sale: Sale, buyer = sale.buyer, buyerUsd = sale.buyerUsd): Promise<string> {
+      return program.methods
+        .claimRebate()
+        .accountsPartial({
+          payer: buyer.publicKey,
+          buyer: buyer.publicKey,
+          usdMint: sale.usdMint,
+          presale: sale.presale,
+          buyerAta: buyerUsd,
+          vaultUsd: sale.vaultUsd,
+          user: userInfoOf(sale, buyer.publicKey),
+          tokenProgram: sale.quoteTokenProgram,
+        })
+        .signers([buyer])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    it("Closes the buyer's account once the rebate is the last thing owed", async () => {
+      const sale = await auctionSale();
+      await buy(sale, usd(10));
+      // Let the price fall below what was paid
+      await waitUntil(now() + 2);
+      await endSale(sale);
+
+      // The rebate is still owed, so claiming every token keeps the account
+      await claim(sale);
+      expect(await connection.getAccountInfo(userInfoOf(sale), "confirmed")).to.not.be.null;
+
+      const before = new anchor.BN(await balanceOf(sale.buyerUsd));
+      const event = await eventOf(await claimRebate(sale), "rebateClaimed");
+      expect(event.rebate.gtn(0)).to.be.true;
+      expect(await balanceOf(sale.buyerUsd)).to.equal(before.add(event.rebate).toString());
+      expect(await connection.getAccountInfo(userInfoOf(sale), "confirmed")).to.be.null;
+    });
+
+    it("Pays a referrer's quote commission on the clearing price", async () => {
+      const sale = await auctionSale();
+      const setReferralConfig = (commissionBps: number) => program.methods
+        .setReferralConfig({ commissionBps, payout: { quote: {} }, maxCommission: new anchor.BN(0) })
+        .accountsPartial({ admin: authority.publicKey, presale: sale.presale })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+      await expectError(setReferralConfig(2_001), "InvalidReferralConfig");
+      await setReferralConfig(2_000);
+
+      const [referral] = PublicKey.findProgramAddressSync(
+        [Buffer.from("referral"), sale.presale.toBuffer(), authority.publicKey.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .registerReferrer()
+        .accountsPartial({ referrer: authority.publicKey, presale: sale.presale, referral })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+
+      // Nothing is credited until the clearing price is known, 20% of the payment is held back
+      const signature = await buy(sale, usd(10), { referral });
+      expect((await eventOf(signature, "referralCredited")).commission.toString()).to.equal("0");
+      const paid: anchor.BN = (await eventOf(signature, "tokensPurchased")).amountSpent;
+      expect((await program.account.referral.fetch(referral)).deferredReserve.toString()).to.equal(paid.muln(2_000).divn(10_000).toString());
+
+      await waitUntil(now() + 2);
+      await endSale(sale);
+      const rebate = (await eventOf(await claimRebate(sale), "rebateClaimed")).rebate;
+      const settledCost = paid.sub(rebate);
+
+      const referrerAta = await getAssociatedTokenAddress(sale.usdMint, authority.publicKey);
+      const claimed = await eventOf(await program.methods
+        .claimReferral()
+        .accountsPartial({
+          referrer: authority.publicKey,
+          mint: sale.usdMint,
+          presale: sale.presale,
+          referral,
+          referrerAta,
+          vault: sale.vaultUsd,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc({ commitment: "confirmed" }), "referralClaimed");
+
+      // 20% of what the buyer was charged in the end, not of what they paid
+      expect(claimed.amount.gtn(0)).to.be.true;
+      expect(claimed.amount.lte(settledCost.muln(2_000).divn(10_000).addn(1))).to.be.true;
+      expect(await balanceOf(sale.vaultUsd)).to.equal(settledCost.sub(claimed.amount).toString());
+      const presaleData = await program.account.presale.fetch(sale.presale);
+      expect(presaleData.quoteMints[0].reservedAmount.toString()).to.equal("0");
+    });
+  });
+});